@@ -0,0 +1,226 @@
+//! asset allocation by security, currency and user defined asset class
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use chrono::{Duration, NaiveDateTime};
+use rust_decimal::Decimal;
+
+use crate::holdings::{last_prices, Holdings};
+use crate::rebalance;
+use crate::report;
+use crate::viac_pdf::ViacSummary;
+
+/// asset class per ISIN, read from a CSV without header: isin,class
+#[derive(Debug, Default)]
+pub struct AssetClasses(HashMap<String, String>);
+
+impl AssetClasses {
+    pub fn from_file(path: &Path) -> csv::Result<Self> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(path)?;
+        let mut classes = HashMap::new();
+        for record in rdr.records() {
+            let record = record?;
+            if let (Some(isin), Some(class)) = (record.get(0), record.get(1)) {
+                classes.insert(isin.trim().to_string(), class.trim().to_string());
+            }
+        }
+        Ok(Self(classes))
+    }
+
+    fn get(&self, isin: &str) -> &str {
+        self.0.get(isin).map_or("unclassified", String::as_str)
+    }
+}
+
+struct Position {
+    isin: String,
+    title: String,
+    currency: String,
+    value: Decimal,
+}
+
+/// securities held at `at` valued in CHF at their last execution price up to `priced_at`,
+/// cash as its own position
+fn positions(
+    summaries: &[ViacSummary],
+    at: NaiveDateTime,
+    priced_at: NaiveDateTime,
+) -> Vec<Position> {
+    let holdings = Holdings::replay(summaries, at);
+    let prices = last_prices(summaries, priced_at);
+    let mut positions: Vec<Position> = holdings
+        .shares
+        .iter()
+        .filter_map(|(isin, shares)| {
+            let price = prices.get(isin)?;
+            let title = summaries
+                .iter()
+                .find(|s| s.isin() == *isin)
+                .map(|s| s.share_title())
+                .unwrap_or_default();
+            Some(Position {
+                isin: isin.to_string(),
+                title,
+                currency: std::str::from_utf8(&price.price.currency)
+                    .unwrap()
+                    .to_string(),
+                value: shares * price.chf(),
+            })
+        })
+        .filter(|p| !p.value.is_zero())
+        .collect();
+    if !holdings.cash.is_zero() {
+        positions.push(Position {
+            isin: "cash".to_string(),
+            title: "Liquidität".to_string(),
+            currency: "CHF".to_string(),
+            value: holdings.cash,
+        });
+    }
+    positions
+}
+
+fn print_breakdown(by: &str, groups: BTreeMap<String, Decimal>, total: Decimal) {
+    println!("by {by}:");
+    for (group, value) in groups {
+        println!(
+            "  {group}: {} CHF {}%",
+            value.round_dp(2),
+            (value / total * Decimal::ONE_HUNDRED).round_dp(2)
+        );
+    }
+}
+
+fn print_allocation(positions: &[Position], classes: &AssetClasses) {
+    let total: Decimal = positions.iter().map(|p| p.value).sum();
+    println!("total {} CHF", total.round_dp(2));
+    if total.is_zero() {
+        return;
+    }
+    let mut securities = BTreeMap::new();
+    let mut currencies = BTreeMap::new();
+    let mut asset_classes = BTreeMap::new();
+    for p in positions {
+        *securities
+            .entry(format!("{} {}", p.isin, p.title))
+            .or_default() += p.value;
+        *currencies.entry(p.currency.to_string()).or_default() += p.value;
+        let class = if p.isin == "cash" {
+            "cash"
+        } else {
+            classes.get(&p.isin)
+        };
+        *asset_classes.entry(class.to_string()).or_default() += p.value;
+    }
+    print_breakdown("security", securities, total);
+    print_breakdown("currency", currencies, total);
+    print_breakdown("asset class", asset_classes, total);
+}
+
+/// percent of the securities value per ISIN, cash left out
+fn weights(
+    summaries: &[ViacSummary],
+    at: NaiveDateTime,
+    priced_at: NaiveDateTime,
+) -> BTreeMap<String, Decimal> {
+    let positions: Vec<Position> = positions(summaries, at, priced_at)
+        .into_iter()
+        .filter(|p| p.isin != "cash")
+        .collect();
+    let total: Decimal = positions.iter().map(|p| p.value).sum();
+    if total.is_zero() {
+        return BTreeMap::new();
+    }
+    positions
+        .into_iter()
+        .map(|p| (p.isin, p.value / total * Decimal::ONE_HUNDRED))
+        .collect()
+}
+
+/// How far the weights moved from right after the trade event `from` until the next one `to`.
+/// Prices are only known from trades, the shares held until `to` are valued at the prices it traded at.
+/// Half the sum of the weight differences is the share of the portfolio off its allocation,
+/// returned with the ISIN that moved most
+fn drift(
+    summaries: &[ViacSummary],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Option<(Decimal, String, Decimal)> {
+    let after = weights(summaries, from, from);
+    let before_next = weights(summaries, to - Duration::days(1), to);
+    let isins: BTreeSet<&String> = after.keys().chain(before_next.keys()).collect();
+    let diffs: Vec<(&String, Decimal)> = isins
+        .into_iter()
+        .map(|isin| {
+            let a = after.get(isin).copied().unwrap_or_default();
+            let b = before_next.get(isin).copied().unwrap_or_default();
+            (isin, b - a)
+        })
+        .collect();
+    let drift: Decimal = diffs.iter().map(|(_, d)| d.abs()).sum::<Decimal>() / Decimal::TWO;
+    let (isin, largest) = diffs.into_iter().max_by_key(|(_, d)| d.abs())?;
+    Some((drift, isin.to_string(), largest))
+}
+
+fn print_drift(summaries: &[ViacSummary]) {
+    let events = rebalance::events(summaries);
+    println!("drift between trade events:");
+    for pair in events.windows(2) {
+        if let Some((drift, isin, largest)) = drift(summaries, pair[0].date, pair[1].date) {
+            println!(
+                "  {} to {} ({}): {}% off, most {isin} {}%",
+                pair[0].date.date(),
+                pair[1].date.date(),
+                pair[1].trigger,
+                drift.round_dp(2),
+                largest.round_dp(2)
+            );
+        }
+    }
+}
+
+/// allocation per portfolio and over all portfolios at `at`, then the drift between trade events
+pub fn report(
+    all_docs: &HashMap<String, Vec<ViacSummary>>,
+    at: NaiveDateTime,
+    classes: &AssetClasses,
+) {
+    let mut all_positions = vec![];
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        println!("== allocation of portfolio {portfolio} at {} ==", at.date());
+        let positions = positions(summaries, at, at);
+        print_allocation(&positions, classes);
+        print_drift(summaries);
+        all_positions.extend(positions);
+    }
+    println!("== allocation of all portfolios at {} ==", at.date());
+    print_allocation(&all_positions, classes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{summary, trade};
+    use crate::viac_pdf::{midnight, ViacDocument};
+
+    #[test]
+    fn drift_follows_the_prices_of_the_next_event() {
+        let buy = |date, isin, shares, price| {
+            summary(ViacDocument::Purchase(trade(date, isin, shares, price)))
+        };
+        let docs = [
+            buy(midnight(2023, 1, 10), "CH0000000001", "10", "CHF 100.00"),
+            buy(midnight(2023, 1, 10), "CH0000000002", "10", "CHF 100.00"),
+            buy(midnight(2023, 1, 10), "CH0000000003", "10", "CHF 100.00"),
+            buy(midnight(2023, 6, 10), "CH0000000001", "1", "CHF 160.00"),
+        ];
+        // a third each, then 1600 of 3600 is 44.44%
+        let (drift, isin, largest) =
+            drift(&docs, midnight(2023, 1, 10), midnight(2023, 6, 10)).unwrap();
+        assert_eq!(drift.round_dp(2).to_string(), "11.11");
+        assert_eq!(isin, "CH0000000001");
+        assert_eq!(largest.round_dp(2).to_string(), "11.11");
+    }
+}
@@ -0,0 +1,89 @@
+//! running balance of the cash account of each portfolio
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDateTime;
+use log::warn;
+use rust_decimal::Decimal;
+
+use crate::money::CHF;
+use crate::report;
+use crate::viac_pdf::ViacSummary;
+
+/// Balance at the end of each day with a cash flow, VIAC settles a deposit
+/// and the purchases it pays on the same day, so only the end of day counts
+fn end_of_day_balances(flows: &[(NaiveDateTime, Decimal)]) -> Vec<(NaiveDateTime, Decimal)> {
+    let mut days: BTreeMap<NaiveDateTime, Decimal> = BTreeMap::new();
+    for (date, amount) in flows {
+        *days.entry(*date).or_default() += amount;
+    }
+    let mut balance = Decimal::ZERO;
+    days.into_iter()
+        .map(|(date, amount)| {
+            balance += amount;
+            (date, balance)
+        })
+        .collect()
+}
+
+/// prints every cash flow with the running balance and flags days ending below zero
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        let mut summaries: Vec<&ViacSummary> = summaries.iter().collect();
+        summaries.sort_by_key(|s| s.valuta_date());
+        println!("== cash of portfolio {portfolio} ==");
+        let mut flows = vec![];
+        let mut balance = Decimal::ZERO;
+        for s in summaries {
+            let Some(flow) = s.cash_flow() else {
+                continue;
+            };
+            if flow.currency != CHF {
+                warn!("{}: cash flow not in CHF {flow}", s.comment);
+            }
+            balance += flow.amount;
+            flows.push((s.valuta_date(), flow.amount));
+            println!(
+                "{} {:<18} {:>12} {:>12}  {}",
+                s.valuta_date().date(),
+                s.order_type(),
+                flow.amount,
+                balance,
+                s.comment
+            );
+        }
+        let negative: Vec<(NaiveDateTime, Decimal)> = end_of_day_balances(&flows)
+            .into_iter()
+            .filter(|(_, b)| *b < Decimal::ZERO)
+            .collect();
+        if negative.is_empty() {
+            println!("balance never negative");
+        }
+        for (date, balance) in negative {
+            println!(
+                "NEGATIVE balance {balance} at end of {}, a PDF is likely missing or a sign wrong",
+                date.date()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::midnight;
+
+    #[test]
+    fn same_day_purchase_of_deposit_is_not_negative() {
+        let day = |d| midnight(2023, 1, d);
+        let flows = [
+            (day(2), Decimal::from(-900)),
+            (day(2), Decimal::from(1000)),
+            (day(5), Decimal::from(-200)),
+        ];
+        let balances = end_of_day_balances(&flows);
+        assert_eq!(
+            balances,
+            vec![(day(2), Decimal::from(100)), (day(5), Decimal::from(-100))]
+        );
+    }
+}
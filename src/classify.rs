@@ -0,0 +1,126 @@
+//! declarative classification of VIAC documents by marker phrases
+use log::{debug, warn};
+use serde::Deserialize;
+
+/// The kind of VIAC document a rule stands for, payload is extracted afterwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum DocumentKind {
+    Purchase,
+    Sale,
+    Dividend,
+    DividendCorrection,
+    TaxReturn,
+    Fees,
+    FeesRefund,
+    Interest,
+    InterestCharge,
+    Incoming,
+    TransferIn,
+    TransferOut,
+    DeliveryIn,
+    DeliveryOut,
+    AnnualStatement,
+    ContributionCertificate,
+    TaxStatement,
+}
+
+/// what `classify` picks from, the builtin rule tables and the rules of user templates
+pub trait Rule {
+    fn name(&self) -> &str;
+    fn kind(&self) -> DocumentKind;
+    fn matches(&self, text: &str) -> bool;
+}
+
+/// A rule matches when the text contains all markers and none of the exclusions,
+/// a marker or exclusion starting with `^` only counts at the start of a line
+#[derive(Debug)]
+pub struct ClassificationRule {
+    pub name: &'static str,
+    pub markers: &'static [&'static str],
+    pub exclusions: &'static [&'static str],
+    pub kind: DocumentKind,
+}
+
+impl Rule for ClassificationRule {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn kind(&self) -> DocumentKind {
+        self.kind
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        self.markers.iter().all(|m| contains_marker(text, m))
+            && !self.exclusions.iter().any(|e| contains_marker(text, e))
+    }
+}
+
+/// `^Title` only matches a line starting with `Title`, document titles are also mentioned in running text
+pub fn contains_marker(text: &str, marker: &str) -> bool {
+    match marker.strip_prefix('^') {
+        Some(title) => text.lines().any(|line| line.starts_with(title)),
+        None => text.contains(marker),
+    }
+}
+
+/// Returns the first matching rule of the table, warns when the table is ambiguous for the text
+pub fn classify<'r, R: Rule>(filename: &str, text: &str, rules: &'r [R]) -> Option<&'r R> {
+    let matching: Vec<&R> = rules.iter().filter(|r| r.matches(text)).collect();
+    if matching.len() > 1 {
+        let names: Vec<&str> = matching.iter().map(|r| r.name()).collect();
+        warn!(
+            "{filename}: several classification rules match {names:?}, using {:?}",
+            matching[0].name()
+        );
+    }
+    if let Some(rule) = matching.first() {
+        debug!(
+            "{filename}: classified as {:?} by rule {:?}",
+            rule.kind(),
+            rule.name()
+        );
+    }
+    matching.first().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &[ClassificationRule] = &[
+        ClassificationRule {
+            name: "refund",
+            markers: &["Dividende", "Rückerstattung"],
+            exclusions: &[],
+            kind: DocumentKind::TaxReturn,
+        },
+        ClassificationRule {
+            name: "dividend",
+            markers: &["Dividende"],
+            exclusions: &["Rückerstattung"],
+            kind: DocumentKind::Dividend,
+        },
+    ];
+
+    #[test]
+    fn exclusions_keep_rules_apart() {
+        let rule = classify("t.pdf", "Dividende\nRückerstattung", RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::TaxReturn);
+        let rule = classify("t.pdf", "Dividende", RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::Dividend);
+        assert!(classify("t.pdf", "Zinsgutschrift", RULES).is_none());
+    }
+
+    #[test]
+    fn anchored_markers_only_match_at_line_start() {
+        assert!(contains_marker("Steuerauszug 2023", "^Steuerauszug"));
+        assert!(contains_marker(
+            "Konto
+Steuerauszug 2023",
+            "^Steuerauszug"
+        ));
+        assert!(!contains_marker("siehe Steuerauszug", "^Steuerauszug"));
+        assert!(contains_marker("siehe Steuerauszug", "Steuerauszug"));
+    }
+}
@@ -0,0 +1,192 @@
+//! yearly pillar 3a contributions checked against the legal limits
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use chrono::{Datelike, Local};
+use log::warn;
+use rust_decimal::Decimal;
+
+use crate::options::ReportArgs;
+use crate::viac_pdf::{ProductType, ViacDocument, ViacSummary};
+
+/// maximum 3a contributions in CHF: (year, employed with pension fund, self-employed without)
+const LIMITS: &[(i32, i64, i64)] = &[
+    (2019, 6826, 34128),
+    (2020, 6826, 34128),
+    (2021, 6883, 34416),
+    (2022, 6883, 34416),
+    (2023, 7056, 35280),
+    (2024, 7056, 35280),
+    (2025, 7258, 36288),
+    (2026, 7258, 36288),
+];
+
+struct Limits(BTreeMap<i32, (Decimal, Decimal)>);
+
+impl Limits {
+    fn builtin() -> Self {
+        Self(
+            LIMITS
+                .iter()
+                .map(|(year, employed, self_employed)| {
+                    (
+                        *year,
+                        (Decimal::from(*employed), Decimal::from(*self_employed)),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// CSV without header, one `year,employed,self_employed` line per year
+    fn from_file(path: &Path) -> csv::Result<Self> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(path)?;
+        let mut limits = BTreeMap::new();
+        for record in rdr.deserialize() {
+            let (year, employed, self_employed): (i32, Decimal, Decimal) = record?;
+            limits.insert(year, (employed, self_employed));
+        }
+        Ok(Self(limits))
+    }
+
+    fn get(&self, year: i32, self_employed: bool) -> Option<Decimal> {
+        self.0
+            .get(&year)
+            .map(|(employed, s)| if self_employed { *s } else { *employed })
+    }
+}
+
+/// deposits into 3a accounts per person and year, contracts not assigned to a person stand for themselves
+fn yearly_deposits<'a>(
+    all_docs: &'a HashMap<String, Vec<ViacSummary>>,
+    persons: &HashMap<&'a str, &'a str>,
+) -> BTreeMap<&'a str, BTreeMap<i32, Decimal>> {
+    let mut deposits: BTreeMap<&str, BTreeMap<i32, Decimal>> = BTreeMap::new();
+    for s in all_docs.values().flatten() {
+        if !matches!(s.document_type, ViacDocument::Incoming(_)) {
+            continue;
+        }
+        if s.product_type == ProductType::VestedBenefits {
+            continue;
+        }
+        let person = persons
+            .get(s.account_number.as_str())
+            .copied()
+            .unwrap_or(s.account_number.as_str());
+        if let Some(deposit) = s.cash_flow() {
+            *deposits
+                .entry(person)
+                .or_default()
+                .entry(s.valuta_date().year())
+                .or_default() += deposit.amount;
+        }
+    }
+    deposits
+}
+
+pub(crate) fn report(all_docs: &HashMap<String, Vec<ViacSummary>>, args: &ReportArgs) {
+    let limits = match &args.limits {
+        Some(path) => match Limits::from_file(path) {
+            Ok(limits) => limits,
+            Err(e) => {
+                warn!("reading limits {} failed: {e}", path.display());
+                return;
+            }
+        },
+        None => Limits::builtin(),
+    };
+    let persons: HashMap<&str, &str> = args
+        .person
+        .iter()
+        .map(|p| (p.contract.as_str(), p.person.as_str()))
+        .collect();
+    let deposits = yearly_deposits(all_docs, &persons);
+    let current_year = Local::now().year();
+    for (person, years) in deposits {
+        let self_employed = args.self_employed.iter().any(|p| p == person);
+        println!("== contributions of {person} ==");
+        for (year, total) in years {
+            match limits.get(year, self_employed) {
+                Some(limit) if total > limit => {
+                    println!(
+                        "{year}: {total} of {limit} CHF, OVER LIMIT by {}",
+                        total - limit
+                    )
+                }
+                Some(limit) if year == current_year => {
+                    println!(
+                        "{year}: {total} of {limit} CHF, {} left this year",
+                        limit - total
+                    )
+                }
+                Some(limit) => println!("{year}: {total} of {limit} CHF"),
+                None => println!("{year}: {total} CHF, no limit known, add it with --limits"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{cash, summary, trade};
+    use crate::viac_pdf::midnight;
+
+    #[test]
+    fn deposits_add_up_per_person_and_year() {
+        let deposit = |contract: &str, product_type, date, amount| {
+            let mut s = summary(ViacDocument::Incoming(cash(date, amount)));
+            s.account_number = contract.to_string();
+            s.product_type = product_type;
+            s
+        };
+        let mut all_docs = HashMap::new();
+        all_docs.insert(
+            "1".to_string(),
+            vec![
+                deposit(
+                    "1000",
+                    ProductType::Pillar3a,
+                    midnight(2023, 1, 5),
+                    "CHF 4'000.00",
+                ),
+                deposit(
+                    "2000",
+                    ProductType::Pillar3a,
+                    midnight(2023, 2, 5),
+                    "CHF 3'056.00",
+                ),
+                deposit(
+                    "2000",
+                    ProductType::Pillar3a,
+                    midnight(2024, 2, 5),
+                    "CHF 100.00",
+                ),
+                deposit(
+                    "3000",
+                    ProductType::VestedBenefits,
+                    midnight(2023, 3, 5),
+                    "CHF 50'000.00",
+                ),
+                summary(ViacDocument::Purchase(trade(
+                    midnight(2023, 1, 6),
+                    "CH0",
+                    "10",
+                    "CHF 100.00",
+                ))),
+            ],
+        );
+        let persons = HashMap::from([("1000", "anna"), ("2000", "anna")]);
+        let deposits = yearly_deposits(&all_docs, &persons);
+        assert_eq!(deposits.len(), 1);
+        assert_eq!(deposits["anna"][&2023].to_string(), "7056.00");
+        assert_eq!(deposits["anna"][&2024].to_string(), "100.00");
+
+        let limits = Limits::builtin();
+        assert_eq!(limits.get(2023, false), Some(Decimal::from(7056)));
+        assert_eq!(limits.get(2023, true), Some(Decimal::from(35280)));
+        assert_eq!(limits.get(2018, false), None);
+    }
+}
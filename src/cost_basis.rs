@@ -0,0 +1,310 @@
+//! cost basis of the positions and realized gains of sales, by moving average and FIFO
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use chrono::NaiveDateTime;
+use log::warn;
+use rust_decimal::Decimal;
+
+use crate::holdings::{delivery_chf_rate, last_prices, LastPrice};
+use crate::money::CHF;
+use crate::report;
+use crate::viac_pdf::{ViacDocument, ViacSummary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// sales take the average cost of all shares held
+    Average,
+    /// sales take the cost of the oldest shares first
+    Fifo,
+}
+
+/// shares bought together and what they cost, in CHF and trade currency
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Lot {
+    pub shares: Decimal,
+    pub chf: Decimal,
+    pub trade: Decimal,
+}
+
+impl Lot {
+    /// the part of the lot for `shares`, costs split proportionally
+    fn part(&self, shares: Decimal) -> Lot {
+        let ratio = shares / self.shares;
+        Lot {
+            shares,
+            chf: self.chf * ratio,
+            trade: self.trade * ratio,
+        }
+    }
+
+    fn add(&mut self, other: Lot) {
+        self.shares += other.shares;
+        self.chf += other.chf;
+        self.trade += other.trade;
+    }
+}
+
+/// the lots held of one ISIN
+#[derive(Debug)]
+pub struct Position {
+    method: Method,
+    lots: VecDeque<Lot>,
+}
+
+impl Position {
+    pub fn new(method: Method) -> Self {
+        Self {
+            method,
+            lots: VecDeque::new(),
+        }
+    }
+
+    pub fn buy(&mut self, lot: Lot) {
+        match (self.method, self.lots.front_mut()) {
+            (Method::Average, Some(average)) => average.add(lot),
+            _ => self.lots.push_back(lot),
+        }
+    }
+
+    /// removes `shares` and returns their cost, never more than held
+    pub fn remove(&mut self, mut shares: Decimal) -> Lot {
+        let mut cost = Lot::default();
+        while shares > Decimal::ZERO {
+            let Some(lot) = self.lots.front_mut() else {
+                warn!("removing {shares} shares more than held");
+                break;
+            };
+            if lot.shares <= shares {
+                shares -= lot.shares;
+                cost.add(*lot);
+                self.lots.pop_front();
+            } else {
+                let part = lot.part(shares);
+                *lot = lot.part(lot.shares - shares);
+                cost.add(part);
+                shares = Decimal::ZERO;
+            }
+        }
+        cost
+    }
+
+    pub fn held(&self) -> Lot {
+        self.lots.iter().fold(Lot::default(), |mut sum, lot| {
+            sum.add(*lot);
+            sum
+        })
+    }
+}
+
+/// gain of a sale against the cost of the shares sold
+#[derive(Debug)]
+pub struct Realized {
+    pub date: NaiveDateTime,
+    pub isin: String,
+    pub proceeds: Lot,
+    pub cost: Lot,
+}
+
+impl Realized {
+    pub fn gain_chf(&self) -> Decimal {
+        self.proceeds.chf - self.cost.chf
+    }
+
+    pub fn gain_trade(&self) -> Decimal {
+        self.proceeds.trade - self.cost.trade
+    }
+}
+
+/// Replays the trades and deliveries of a portfolio,
+/// purchases cost the settled amount including costs, sales yield it net of costs,
+/// in CHF and converted back to the trade currency at the rate of the trade.
+/// Deliveries move shares at their reference value converted to CHF without realizing a gain,
+/// without a rate the shares still move and only their CHF cost is left at zero.
+pub fn replay(
+    summaries: &[ViacSummary],
+    method: Method,
+) -> (BTreeMap<String, Position>, Vec<Realized>) {
+    let mut ordered: Vec<&ViacSummary> = summaries.iter().collect();
+    ordered.sort_by_key(|s| s.valuta_date());
+    let mut positions: BTreeMap<String, Position> = BTreeMap::new();
+    let mut realized = vec![];
+    for s in ordered {
+        let Some((isin, delta)) = s.share_delta() else {
+            continue;
+        };
+        let position = positions
+            .entry(isin.to_string())
+            .or_insert_with(|| Position::new(method));
+        let lot = match &s.document_type {
+            ViacDocument::Purchase(t) | ViacDocument::Sale(t) => {
+                if t.valuta_price().currency != CHF {
+                    warn!("{}: settled in {}", s.comment, t.valuta_price());
+                }
+                Lot {
+                    shares: delta.abs(),
+                    chf: t.valuta_price().amount,
+                    trade: (t.valuta_price().amount / t.chf_rate()).round_dp(2),
+                }
+            }
+            ViacDocument::DeliveryIn(d) | ViacDocument::DeliveryOut(d) => {
+                let rate = delivery_chf_rate(summaries, s, d);
+                if rate.is_none() && matches!(s.document_type, ViacDocument::DeliveryIn(_)) {
+                    warn!(
+                        "{}: no exchange rate for the delivery valued {}, its CHF cost is unknown and taken as zero",
+                        s.comment,
+                        d.value()
+                    );
+                }
+                Lot {
+                    shares: delta.abs(),
+                    chf: rate.map_or(Decimal::ZERO, |rate| (d.value().amount * rate).round_dp(2)),
+                    trade: d.value().amount,
+                }
+            }
+            _ => unreachable!(),
+        };
+        match &s.document_type {
+            ViacDocument::Purchase(_) | ViacDocument::DeliveryIn(_) => position.buy(lot),
+            ViacDocument::Sale(_) => {
+                let cost = position.remove(lot.shares);
+                realized.push(Realized {
+                    date: s.valuta_date(),
+                    isin: isin.to_string(),
+                    proceeds: lot,
+                    cost,
+                });
+            }
+            _ => {
+                position.remove(lot.shares);
+            }
+        }
+    }
+    (positions, realized)
+}
+
+/// realized gains of every sale and unrealized gains at the last execution price, per portfolio
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        println!("== cost basis of portfolio {portfolio} ==");
+        let prices = last_prices(summaries, NaiveDateTime::MAX);
+        let (average, average_realized) = replay(summaries, Method::Average);
+        let (fifo, fifo_realized) = replay(summaries, Method::Fifo);
+        for (a, f) in average_realized.iter().zip(fifo_realized.iter()) {
+            println!(
+                "sale {} {} {} shares for {} CHF: gain average {} CHF / {} FIFO {} CHF / {}",
+                a.date.date(),
+                a.isin,
+                a.proceeds.shares,
+                a.proceeds.chf.round_dp(2),
+                a.gain_chf().round_dp(2),
+                a.gain_trade().round_dp(2),
+                f.gain_chf().round_dp(2),
+                f.gain_trade().round_dp(2),
+            );
+        }
+        for (isin, position) in &average {
+            let held = position.held();
+            if held.shares.is_zero() {
+                continue;
+            }
+            let fifo_held = fifo[isin].held();
+            match prices.get(isin) {
+                Some(price) => print_unrealized(isin, price, held, fifo_held),
+                None => println!(
+                    "{isin}: {} shares cost {} CHF, no trade price known",
+                    held.shares,
+                    held.chf.round_dp(2)
+                ),
+            }
+        }
+    }
+}
+
+fn print_unrealized(isin: &str, price: &LastPrice, average: Lot, fifo: Lot) {
+    let value_chf = average.shares * price.chf();
+    let value_trade = average.shares * price.price.amount;
+    println!(
+        "{isin}: {} shares at {} of {}: value {} CHF, unrealized average {} CHF / {} FIFO {} CHF / {}",
+        average.shares,
+        price.price,
+        price.date.date(),
+        value_chf.round_dp(2),
+        (value_chf - average.chf).round_dp(2),
+        (value_trade - average.trade).round_dp(2),
+        (value_chf - fifo.chf).round_dp(2),
+        (value_trade - fifo.trade).round_dp(2),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{delivery, foreign_trade, summary, with_cost};
+    use crate::viac_pdf::{midnight, CostKind};
+
+    fn lot(shares: i64, chf: i64) -> Lot {
+        Lot {
+            shares: Decimal::from(shares),
+            chf: Decimal::from(chf),
+            trade: Decimal::from(chf),
+        }
+    }
+
+    #[test]
+    fn average_and_fifo_cost_of_a_sale() {
+        let mut average = Position::new(Method::Average);
+        let mut fifo = Position::new(Method::Fifo);
+        for p in [&mut average, &mut fifo] {
+            p.buy(lot(10, 100));
+            p.buy(lot(10, 200));
+        }
+        assert_eq!(average.remove(Decimal::from(15)).chf, Decimal::from(225));
+        assert_eq!(fifo.remove(Decimal::from(15)).chf, Decimal::from(200));
+        assert_eq!(average.held(), lot(5, 75));
+        assert_eq!(fifo.held(), lot(5, 100));
+    }
+
+    #[test]
+    fn foreign_sale_gains_include_costs_and_rateless_deliveries_move_shares() {
+        let docs = [
+            summary(ViacDocument::Purchase(with_cost(
+                foreign_trade(
+                    midnight(2023, 1, 10),
+                    "IE00B5BMR087",
+                    "10",
+                    "USD 100.00",
+                    "0.9",
+                ),
+                CostKind::Other,
+                "CHF 9.00",
+            ))),
+            summary(ViacDocument::Sale(foreign_trade(
+                midnight(2023, 6, 10),
+                "IE00B5BMR087",
+                "5",
+                "USD 120.00",
+                "0.9",
+            ))),
+            summary(ViacDocument::DeliveryIn(delivery(
+                midnight(2023, 7, 1),
+                "IE00B4L5Y983",
+                "3",
+                "USD 80.00",
+            ))),
+            summary(ViacDocument::DeliveryOut(delivery(
+                midnight(2023, 8, 1),
+                "IE00B5BMR087",
+                "2",
+                "EUR 110.00",
+            ))),
+        ];
+        let (positions, realized) = replay(&docs, Method::Average);
+        // cost 909 CHF, 1010 USD for 10 shares
+        assert_eq!(realized[0].gain_chf().round_dp(2).to_string(), "85.50");
+        assert_eq!(realized[0].gain_trade().round_dp(2).to_string(), "95.00");
+        let delivered = positions["IE00B4L5Y983"].held();
+        assert_eq!(delivered.shares, Decimal::from(3));
+        assert_eq!(delivered.chf, Decimal::ZERO);
+        assert_eq!(positions["IE00B5BMR087"].held().shares, Decimal::from(3));
+    }
+}
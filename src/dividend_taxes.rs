@@ -0,0 +1,165 @@
+//! withholding tax refunds matched to the dividends they refund
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDateTime;
+use log::{info, warn};
+use rust_decimal::Decimal;
+
+use crate::money::CHF;
+use crate::viac_pdf::{ViacDocument, ViacSummary};
+
+/// what a refund and a dividend are matched on
+struct Payout {
+    isin: String,
+    ex_date: Option<NaiveDateTime>,
+    valuta_date: NaiveDateTime,
+}
+
+impl Payout {
+    fn of(summary: &ViacSummary) -> Option<Self> {
+        match &summary.document_type {
+            ViacDocument::Dividend(d) | ViacDocument::TaxReturn(d) => Some(Self {
+                isin: summary.isin(),
+                ex_date: d.ex_date,
+                valuta_date: summary.valuta_date(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Refunds name the ex-date of their dividend when VIAC prints it,
+/// otherwise the refund belongs to the last dividend of the ISIN paid before it
+fn find_dividend(refund: &Payout, dividends: &[(usize, Payout)]) -> Option<usize> {
+    let same_isin = || dividends.iter().filter(|(_, d)| d.isin == refund.isin);
+    if refund.ex_date.is_some() {
+        if let Some((i, _)) = same_isin().find(|(_, d)| d.ex_date == refund.ex_date) {
+            return Some(*i);
+        }
+    }
+    same_isin()
+        .filter(|(_, d)| d.valuta_date <= refund.valuta_date)
+        .max_by_key(|(_, d)| d.valuta_date)
+        .map(|(i, _)| *i)
+}
+
+/// pairs of (refund, dividend) indices into summaries, dividend None when it is not in the input
+fn match_refunds(summaries: &[ViacSummary]) -> Vec<(usize, Option<usize>)> {
+    let dividends: Vec<(usize, Payout)> = summaries
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| matches!(s.document_type, ViacDocument::Dividend(_)))
+        .filter_map(|(i, s)| Payout::of(s).map(|p| (i, p)))
+        .collect();
+    summaries
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| matches!(s.document_type, ViacDocument::TaxReturn(_)))
+        .filter_map(|(i, s)| Payout::of(s).map(|p| (i, find_dividend(&p, &dividends))))
+        .collect()
+}
+
+/// notes the refunded dividend in the comment of each refund of the portfolio
+pub fn link_refunds(summaries: &mut [ViacSummary]) {
+    for (refund, dividend) in match_refunds(summaries) {
+        match dividend {
+            Some(dividend) => {
+                let date = summaries[dividend].valuta_date().date();
+                info!("{}: refunds dividend of {date}", summaries[refund].comment);
+                summaries[refund].comment += &format!(" refunds dividend of {date}");
+            }
+            None => warn!(
+                "{}: no dividend found for this tax refund",
+                summaries[refund].comment
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+struct IsinTaxes {
+    gross: Decimal,
+    withheld: Decimal,
+    refunded: Decimal,
+}
+
+/// per ISIN over all portfolios: gross dividends, withheld and refunded tax in CHF
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) {
+    let mut isins: BTreeMap<(String, String), IsinTaxes> = BTreeMap::new();
+    for summaries in all_docs.values() {
+        for (refund, dividend) in match_refunds(summaries) {
+            if dividend.is_none() {
+                println!(
+                    "{}: refund without its dividend, add the dividend PDF",
+                    summaries[refund].comment
+                );
+            }
+        }
+        for s in summaries {
+            let Some(cash) = s.cash_flow() else {
+                continue;
+            };
+            let key = || (s.isin(), s.share_title());
+            match &s.document_type {
+                ViacDocument::Dividend(_) | ViacDocument::TaxReturn(_) if cash.currency != CHF => {
+                    warn!("{}: {cash} not booked in CHF, skipping it", s.comment);
+                }
+                ViacDocument::Dividend(d) => {
+                    let withheld = d
+                        .withholding_tax_booked()
+                        .map_or(Decimal::ZERO, |t| t.amount);
+                    let t = isins.entry(key()).or_default();
+                    t.gross += cash.amount + withheld;
+                    t.withheld += withheld;
+                }
+                ViacDocument::TaxReturn(_) => {
+                    isins.entry(key()).or_default().refunded += cash.amount;
+                }
+                _ => {}
+            }
+        }
+    }
+    println!("== dividend withholding tax in CHF ==");
+    for ((isin, title), t) in isins {
+        let net = t.withheld - t.refunded;
+        let rate = if t.gross.is_zero() {
+            Decimal::ZERO
+        } else {
+            (net / t.gross * Decimal::ONE_HUNDRED).round_dp(2)
+        };
+        println!(
+            "{isin} {title}: gross {} withheld {} refunded {} net withholding {} ({rate}%)",
+            t.gross, t.withheld, t.refunded, net
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::midnight;
+
+    fn payout(isin: &str, ex_date: Option<(i32, u32, u32)>, valuta: (i32, u32, u32)) -> Payout {
+        let date = |(y, m, d)| midnight(y, m, d);
+        Payout {
+            isin: isin.to_string(),
+            ex_date: ex_date.map(date),
+            valuta_date: date(valuta),
+        }
+    }
+
+    #[test]
+    fn refund_matches_ex_date_then_last_dividend_before() {
+        let dividends = vec![
+            (0, payout("US01", Some((2022, 3, 1)), (2022, 3, 10))),
+            (1, payout("US01", Some((2022, 9, 1)), (2022, 9, 10))),
+            (2, payout("IE02", None, (2022, 11, 10))),
+        ];
+        let refund = payout("US01", Some((2022, 3, 1)), (2023, 1, 20));
+        assert_eq!(find_dividend(&refund, &dividends), Some(0));
+        let refund = payout("US01", None, (2023, 1, 20));
+        assert_eq!(find_dividend(&refund, &dividends), Some(1));
+        let refund = payout("US01", None, (2022, 1, 20));
+        assert_eq!(find_dividend(&refund, &dividends), None);
+    }
+}
@@ -0,0 +1,103 @@
+//! ECB euro foreign exchange reference rates from `eurofxref-hist.zip`
+use std::collections::HashMap;
+use std::io::Read;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EuroFxRefError {
+    #[error("cannot open {0}: {1}")]
+    Open(String, std::io::Error),
+    #[error("not a zip archive with the rates CSV: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("rates CSV unreadable: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("no ECB rate of {0} on {1}")]
+    NotFound(String, String),
+}
+
+/// units of each currency per euro, by day `YYYY-MM-DD`
+#[derive(Debug, Default)]
+pub struct EuroForex {
+    days: HashMap<String, HashMap<[u8; 3], Decimal>>,
+}
+
+impl EuroForex {
+    /// CSV with header `Date,USD,JPY,...`, days without a rate of a currency say `N/A`
+    fn from_reader(rdr: impl Read) -> Result<Self, EuroFxRefError> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(rdr);
+        let currencies: Vec<Option<[u8; 3]>> = rdr
+            .headers()?
+            .iter()
+            .map(|h| h.as_bytes().try_into().ok())
+            .collect();
+        let mut days = HashMap::new();
+        for record in rdr.records() {
+            let record = record?;
+            let Some(date) = record.get(0) else {
+                continue;
+            };
+            let rates = record
+                .iter()
+                .zip(&currencies)
+                .skip(1)
+                .filter_map(|(rate, currency)| Some(((*currency)?, Decimal::from_str(rate).ok()?)))
+                .collect();
+            days.insert(date.to_string(), rates);
+        }
+        Ok(Self { days })
+    }
+
+    /// units of `currency` per euro on `date` (`YYYY-MM-DD`)
+    pub fn fetch(&self, date: &str, currency: [u8; 3]) -> Result<Decimal, EuroFxRefError> {
+        if &currency == b"EUR" {
+            return Ok(Decimal::ONE);
+        }
+        self.days
+            .get(date)
+            .and_then(|rates| rates.get(&currency))
+            .copied()
+            .ok_or_else(|| {
+                EuroFxRefError::NotFound(
+                    String::from_utf8_lossy(&currency).into_owned(),
+                    date.to_string(),
+                )
+            })
+    }
+}
+
+pub static EURO_FOREX: Lazy<Mutex<EuroForex>> = Lazy::new(|| Mutex::new(EuroForex::default()));
+
+/// loads the rates CSV of the zipped ECB history into `EURO_FOREX`
+pub fn read_csv(path: &str) -> Result<(), EuroFxRefError> {
+    let file = std::fs::File::open(path).map_err(|e| EuroFxRefError::Open(path.to_string(), e))?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    let forex = EuroForex::from_reader(archive.by_index(0)?)?;
+    *EURO_FOREX.lock().unwrap() = forex;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_rates_per_day_and_currency() {
+        let csv =
+            "Date,USD,CHF,ISK,\n2023-03-21,1.0772,0.9953,N/A,\n2023-03-20,1.0717,0.9946,150.3,\n";
+        let forex = EuroForex::from_reader(csv.as_bytes()).unwrap();
+        assert_eq!(
+            forex.fetch("2023-03-21", *b"CHF").unwrap(),
+            Decimal::from_str("0.9953").unwrap()
+        );
+        assert_eq!(forex.fetch("2023-03-21", *b"EUR").unwrap(), Decimal::ONE);
+        assert!(forex.fetch("2023-03-21", *b"ISK").is_err());
+        assert!(forex.fetch("2023-03-19", *b"USD").is_err());
+    }
+}
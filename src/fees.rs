@@ -0,0 +1,146 @@
+//! yearly costs of a portfolio relative to the capital invested
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, Duration, Months, NaiveDateTime};
+use log::warn;
+use rust_decimal::Decimal;
+
+use crate::money::CHF;
+use crate::performance::value_at;
+use crate::report;
+use crate::viac_pdf::{midnight, CostKind, ViacDocument, ViacSummary};
+
+#[derive(Debug, Default)]
+struct YearCosts {
+    /// Verwaltungsgebühr less refunds
+    management: Decimal,
+    stamp_duty: Decimal,
+    /// currency exchange fees listed on trades
+    fx: Decimal,
+    /// brokerage and other trade fees
+    other: Decimal,
+}
+
+impl YearCosts {
+    fn total(&self) -> Decimal {
+        self.management + self.stamp_duty + self.fx + self.other
+    }
+}
+
+fn year_costs(summaries: &[ViacSummary]) -> BTreeMap<i32, YearCosts> {
+    let mut years: BTreeMap<i32, YearCosts> = BTreeMap::new();
+    for s in summaries {
+        let year = years.entry(s.valuta_date().year()).or_default();
+        match &s.document_type {
+            ViacDocument::Fees(_) | ViacDocument::FeesRefund(_) => {
+                if let Some(flow) = s.cash_flow() {
+                    year.management -= flow.amount;
+                }
+            }
+            ViacDocument::Purchase(t) | ViacDocument::Sale(t) => {
+                for cost in t.costs() {
+                    let Some(booked) = t.cost_booked(cost) else {
+                        continue;
+                    };
+                    if booked.currency != CHF {
+                        warn!("{}: cost not in CHF {booked}", s.comment);
+                    }
+                    let amount = booked.amount.abs();
+                    match cost.kind {
+                        CostKind::StampDuty => year.stamp_duty += amount,
+                        CostKind::CurrencyExchange => year.fx += amount,
+                        CostKind::Brokerage | CostKind::Other => year.other += amount,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    years
+}
+
+/// average of the month end values of the year up to `last`
+fn average_capital(summaries: &[ViacSummary], year: i32, last: NaiveDateTime) -> Decimal {
+    let values: Vec<Decimal> = (1..=12)
+        .map(|month| midnight(year, month, 1) + Months::new(1) - Duration::days(1))
+        .filter(|d| *d <= last)
+        .map(|d| value_at(summaries, d))
+        .filter(|v| *v > Decimal::ZERO)
+        .collect();
+    if values.is_empty() {
+        return Decimal::ZERO;
+    }
+    values.iter().sum::<Decimal>() / Decimal::from(values.len())
+}
+
+/// per portfolio and year the management fees, stamp duty and FX costs,
+/// with their sum relative to the average invested capital
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        let Some(last) = summaries.iter().map(|s| s.valuta_date()).max() else {
+            continue;
+        };
+        println!("== costs of portfolio {portfolio} in CHF ==");
+        for (year, c) in year_costs(summaries) {
+            let capital = average_capital(summaries, year, last);
+            let percent = if capital.is_zero() {
+                "-".to_string()
+            } else {
+                format!("{:.3}", c.total() / capital * Decimal::ONE_HUNDRED)
+            };
+            println!(
+                "{year}: management {} stamp duty {} FX {} other {} total {} of average capital {} = {percent}%",
+                c.management.round_dp(2),
+                c.stamp_duty.round_dp(2),
+                c.fx.round_dp(2),
+                c.other.round_dp(2),
+                c.total().round_dp(2),
+                capital.round_dp(2),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{cash, foreign_trade, summary, with_cost};
+
+    #[test]
+    fn yearly_costs_against_the_average_capital() {
+        let purchase = foreign_trade(
+            midnight(2023, 2, 10),
+            "IE00B5BMR087",
+            "5",
+            "USD 100.00",
+            "0.9",
+        );
+        let purchase = with_cost(purchase, CostKind::StampDuty, "CHF 1.38");
+        let docs = [
+            summary(ViacDocument::Incoming(cash(
+                midnight(2023, 1, 2),
+                "CHF 1'000.00",
+            ))),
+            summary(ViacDocument::Purchase(with_cost(
+                purchase,
+                CostKind::CurrencyExchange,
+                "CHF 0.50",
+            ))),
+            summary(ViacDocument::Fees(cash(midnight(2023, 3, 31), "CHF 12.30"))),
+            summary(ViacDocument::FeesRefund(cash(
+                midnight(2023, 3, 31),
+                "CHF 2.00",
+            ))),
+        ];
+        let years = year_costs(&docs);
+        let c = &years[&2023];
+        assert_eq!(c.management.to_string(), "10.30");
+        assert_eq!(c.stamp_duty.to_string(), "1.38");
+        assert_eq!(c.fx.to_string(), "0.50");
+        assert!(c.other.is_zero());
+        assert_eq!(c.total().to_string(), "12.18");
+        // month ends of January and February: 1000 cash, then 548.12 cash and 450 of shares
+        let capital = average_capital(&docs, 2023, midnight(2023, 2, 28));
+        assert_eq!(capital.round_dp(2).to_string(), "999.06");
+    }
+}
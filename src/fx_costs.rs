@@ -0,0 +1,125 @@
+//! cost of the currency conversions VIAC applied, against the ECB reference rates
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, Duration, NaiveDateTime};
+use log::warn;
+use rust_decimal::Decimal;
+
+use crate::eurofxref::{self, EURO_FOREX};
+use crate::money::CHF;
+use crate::viac_pdf::{ViacDocument, ViacSummary};
+
+/// the ECB publishes no rates on weekends and holidays
+const MAX_DAYS_BACK: i64 = 5;
+
+/// CHF per unit of `currency` by the ECB reference rates, from the last day with rates up to `date`
+fn reference_rate(date: NaiveDateTime, currency: [u8; 3]) -> Option<Decimal> {
+    let forex = EURO_FOREX.lock().unwrap();
+    (0..=MAX_DAYS_BACK).find_map(|back| {
+        let day = (date - Duration::days(back)).format("%Y-%m-%d").to_string();
+        match (forex.fetch(&day, CHF), forex.fetch(&day, currency)) {
+            (Ok(chf), Ok(foreign)) => Some(chf / foreign),
+            _ => None,
+        }
+    })
+}
+
+/// CHF paid more, or received less, than at the reference rate;
+/// buying foreign currency for a purchase costs when VIAC's rate is higher
+fn conversion_cost(
+    buys_foreign: bool,
+    amount: Decimal,
+    viac: Decimal,
+    reference: Decimal,
+) -> Decimal {
+    let cost = amount.abs() * (viac - reference);
+    if buys_foreign {
+        cost
+    } else {
+        -cost
+    }
+}
+
+#[derive(Default)]
+struct Aggregate {
+    volume: Decimal,
+    cost: Decimal,
+}
+
+/// per conversion the spread and its CHF cost, then totals per currency and year
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) -> anyhow::Result<()> {
+    eurofxref::read_csv("eurofxref-hist.zip")?;
+    let mut summaries: Vec<&ViacSummary> = all_docs.values().flatten().collect();
+    summaries.sort_by_key(|s| s.valuta_date());
+    let mut totals: BTreeMap<(String, i32), Aggregate> = BTreeMap::new();
+    println!("== currency conversions against ECB reference rates ==");
+    for s in summaries {
+        let Some(er) = s.exchange() else {
+            continue;
+        };
+        let foreign = er.foreign();
+        let currency = std::str::from_utf8(&foreign.currency).unwrap().to_string();
+        let Some(reference) = reference_rate(s.valuta_date(), foreign.currency) else {
+            warn!("{}: no ECB rate for {currency}", s.comment);
+            continue;
+        };
+        let buys_foreign = matches!(s.document_type, ViacDocument::Purchase(_));
+        let cost = conversion_cost(buys_foreign, foreign.amount, er.rate(), reference);
+        let volume = foreign.amount.abs() * reference;
+        if volume.is_zero() {
+            continue;
+        }
+        // positive when the conversion cost us, whatever its direction
+        let spread = cost / volume * Decimal::ONE_HUNDRED;
+        println!(
+            "{} {} {foreign}: VIAC {} ECB {} spread {}% cost {} CHF",
+            s.valuta_date().date(),
+            s.order_type(),
+            er.rate(),
+            reference.round_dp(5),
+            spread.round_dp(3),
+            cost.round_dp(2),
+        );
+        let total = totals
+            .entry((currency, s.valuta_date().year()))
+            .or_default();
+        total.volume += volume;
+        total.cost += cost;
+    }
+    println!("== per currency and year ==");
+    for ((currency, year), t) in totals {
+        let spread = if t.volume.is_zero() {
+            Decimal::ZERO
+        } else {
+            t.cost / t.volume * Decimal::ONE_HUNDRED
+        };
+        println!(
+            "{year} {currency}: converted {} CHF, cost {} CHF ({}%)",
+            t.volume.round_dp(2),
+            t.cost.round_dp(2),
+            spread.round_dp(3)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn conversion_cost_sign_follows_direction() {
+        let d = |s| Decimal::from_str(s).unwrap();
+        // buying 1000 USD at 0.92 instead of 0.91 costs 10 CHF
+        assert_eq!(
+            conversion_cost(true, d("1000"), d("0.92"), d("0.91")),
+            d("10")
+        );
+        // selling 1000 USD at 0.90 instead of 0.91 costs 10 CHF too
+        assert_eq!(
+            conversion_cost(false, d("1000"), d("0.90"), d("0.91")),
+            d("10")
+        );
+    }
+}
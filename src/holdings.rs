@@ -0,0 +1,228 @@
+//! positions of a portfolio replayed from its transactions
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDateTime;
+use log::warn;
+use rust_decimal::Decimal;
+
+use crate::money::{Money, CHF};
+use crate::report;
+use crate::viac_pdf::{ViacDelivery, ViacDocument, ViacSummary};
+
+#[derive(Debug, Default)]
+pub struct Holdings {
+    /// shares per ISIN
+    pub shares: BTreeMap<String, Decimal>,
+    /// cash balance in CHF
+    pub cash: Decimal,
+}
+
+impl Holdings {
+    /// replays all documents with a valuta date up to and including `until`
+    pub fn replay<'a>(
+        summaries: impl IntoIterator<Item = &'a ViacSummary>,
+        until: NaiveDateTime,
+    ) -> Self {
+        let mut holdings = Holdings::default();
+        for s in summaries.into_iter().filter(|s| s.valuta_date() <= until) {
+            if let Some((isin, delta)) = s.share_delta() {
+                *holdings.shares.entry(isin.to_string()).or_default() += delta;
+            }
+            if let Some(flow) = s.cash_flow() {
+                if flow.currency != CHF {
+                    warn!("{}: cash flow not in CHF {flow}", s.comment);
+                }
+                holdings.cash += flow.amount;
+            }
+        }
+        holdings
+    }
+
+    /// cash plus the shares valued in CHF at the given prices, shares without a price count as zero
+    pub fn value(&self, prices: &BTreeMap<String, LastPrice>) -> Decimal {
+        let mut value = self.cash;
+        for (isin, shares) in self.shares.iter().filter(|(_, s)| !s.is_zero()) {
+            match prices.get(isin) {
+                Some(price) => value += shares * price.chf(),
+                None => warn!("no price known for {isin}, valued at zero"),
+            }
+        }
+        value
+    }
+}
+
+/// execution price of the most recent trade of an ISIN
+#[derive(Debug, Clone, Copy)]
+pub struct LastPrice {
+    pub date: NaiveDateTime,
+    pub price: Money,
+    /// CHF per unit of the price currency at that trade
+    pub chf_rate: Decimal,
+}
+
+impl LastPrice {
+    pub fn chf(&self) -> Decimal {
+        self.price.amount * self.chf_rate
+    }
+}
+
+/// last execution price per ISIN of the trades up to and including `until`
+pub fn last_prices<'a>(
+    summaries: impl IntoIterator<Item = &'a ViacSummary>,
+    until: NaiveDateTime,
+) -> BTreeMap<String, LastPrice> {
+    let mut prices: BTreeMap<String, LastPrice> = BTreeMap::new();
+    for s in summaries.into_iter().filter(|s| s.valuta_date() <= until) {
+        if let ViacDocument::Purchase(t) | ViacDocument::Sale(t) = &s.document_type {
+            let price = LastPrice {
+                date: s.valuta_date(),
+                price: t.share_price(),
+                chf_rate: t.chf_rate(),
+            };
+            prices
+                .entry(s.isin())
+                .and_modify(|p| {
+                    if p.date <= price.date {
+                        *p = price;
+                    }
+                })
+                .or_insert(price);
+        }
+    }
+    prices
+}
+
+/// CHF per unit of the reference price of a delivery, its own rate
+/// or else the one of the last trade of the ISIN before it in that currency
+pub fn delivery_chf_rate(
+    summaries: &[ViacSummary],
+    s: &ViacSummary,
+    d: &ViacDelivery,
+) -> Option<Decimal> {
+    d.chf_rate.or_else(|| {
+        last_prices(summaries, s.valuta_date())
+            .get(&s.isin())
+            .filter(|p| p.price.currency == d.value().currency)
+            .map(|p| p.chf_rate)
+    })
+}
+
+/// Deliveries in a foreign currency take the exchange rate of the nearest trade of the ISIN
+/// in that currency, the last one before or else the first one after
+pub fn rate_deliveries(summaries: &mut [ViacSummary]) {
+    let trades: Vec<(String, NaiveDateTime, [u8; 3], Decimal)> = summaries
+        .iter()
+        .filter_map(|s| match &s.document_type {
+            ViacDocument::Purchase(t) | ViacDocument::Sale(t) => Some((
+                s.isin(),
+                s.valuta_date(),
+                t.share_price().currency,
+                t.chf_rate(),
+            )),
+            _ => None,
+        })
+        .collect();
+    for s in summaries.iter_mut() {
+        let (isin, date) = (s.isin(), s.valuta_date());
+        let (ViacDocument::DeliveryIn(d) | ViacDocument::DeliveryOut(d)) = &mut s.document_type
+        else {
+            continue;
+        };
+        if d.chf_rate.is_some() {
+            continue;
+        }
+        let currency = d.value().currency;
+        let same = trades
+            .iter()
+            .filter(|(i, _, c, _)| *i == isin && *c == currency);
+        let before = same
+            .clone()
+            .filter(|(_, t, ..)| *t <= date)
+            .max_by_key(|(_, t, ..)| *t);
+        let after = same
+            .filter(|(_, t, ..)| *t > date)
+            .min_by_key(|(_, t, ..)| *t);
+        match before.or(after) {
+            Some((_, _, _, rate)) => d.chf_rate = Some(*rate),
+            None => warn!(
+                "{}: no trade of {isin} to take the exchange rate from, delivery left in {}",
+                s.comment,
+                d.value()
+            ),
+        }
+    }
+}
+
+/// shares and cash of each portfolio after all documents up to `at`
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>, at: NaiveDateTime) {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        let holdings = Holdings::replay(summaries, at);
+        let prices = last_prices(summaries, at);
+        println!("== holdings of portfolio {portfolio} at {} ==", at.date());
+        for (isin, shares) in holdings.shares.iter().filter(|(_, s)| !s.is_zero()) {
+            let title = summaries
+                .iter()
+                .find(|s| s.isin() == *isin)
+                .map(|s| s.share_title())
+                .unwrap_or_default();
+            match prices.get(isin) {
+                Some(p) => println!(
+                    "{isin} {title}: {shares} at {} of {} = {} CHF",
+                    p.price,
+                    p.date.date(),
+                    (shares * p.chf()).round_dp(2)
+                ),
+                None => println!("{isin} {title}: {shares}, no trade price known"),
+            }
+        }
+        println!("cash: {} CHF", holdings.cash);
+        println!("total: {} CHF", holdings.value(&prices).round_dp(2));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{cash, foreign_trade, summary, trade};
+    use crate::viac_pdf::midnight;
+
+    #[test]
+    fn replay_values_shares_at_the_last_price_before() {
+        let docs = [
+            summary(ViacDocument::Incoming(cash(
+                midnight(2023, 1, 2),
+                "CHF 2'000.00",
+            ))),
+            summary(ViacDocument::Purchase(trade(
+                midnight(2023, 1, 3),
+                "CH0017142719",
+                "10",
+                "CHF 100.00",
+            ))),
+            summary(ViacDocument::Purchase(foreign_trade(
+                midnight(2023, 2, 1),
+                "IE00B5BMR087",
+                "5",
+                "USD 100.00",
+                "0.9",
+            ))),
+            summary(ViacDocument::Sale(trade(
+                midnight(2023, 3, 1),
+                "CH0017142719",
+                "4",
+                "CHF 110.00",
+            ))),
+        ];
+        let at = midnight(2023, 2, 15);
+        let holdings = Holdings::replay(&docs, at);
+        assert_eq!(holdings.shares["CH0017142719"], Decimal::from(10));
+        assert_eq!(holdings.cash, Decimal::from(550));
+        assert_eq!(holdings.value(&last_prices(&docs, at)), Decimal::from(2000));
+
+        let at = midnight(2023, 12, 31);
+        let holdings = Holdings::replay(&docs, at);
+        assert_eq!(holdings.shares["CH0017142719"], Decimal::from(6));
+        assert_eq!(holdings.cash, Decimal::from(990));
+        assert_eq!(holdings.value(&last_prices(&docs, at)), Decimal::from(2100));
+    }
+}
@@ -0,0 +1,117 @@
+//! detection of the language a VIAC document is written in
+use log::{debug, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Language {
+    #[value(name = "de")]
+    German,
+    #[value(name = "fr")]
+    French,
+}
+
+/// below this share of matched vocabulary the detection is reported as unreliable
+const LOW_CONFIDENCE: f64 = 0.75;
+
+/// only words a single language uses, e.g. `Valuta` is on French documents too
+const VOCABULARY: &[(Language, &[&str])] = &[
+    (
+        Language::German,
+        &[
+            "Vertrag",
+            "Betrag",
+            "Kurs:",
+            "Börsenabrechnung",
+            "Dividendenausschüttung",
+            "Verwaltungsgebühr",
+            "Zinsgutschrift",
+            "Zahlungseingang",
+            "Umrechnungskurs",
+            "Stempelsteuer",
+        ],
+    ),
+    (
+        Language::French,
+        &[
+            "Contrat",
+            "Portefeuille",
+            "Valeur",
+            "Montant",
+            "Cours:",
+            "de la Banque WIR",
+            "Opération de bourse",
+            "Avis de dividende",
+            "Intérêts",
+            "Avis de versement",
+            "Taux de conversion",
+            "Droits de timbre",
+        ],
+    ),
+];
+
+#[derive(Debug)]
+pub struct Detection {
+    pub language: Language,
+    /// share of all matched vocabulary belonging to the detected language
+    pub confidence: f64,
+}
+
+/// scores the text against the known vocabulary of every supported language
+pub fn detect(text: &str) -> Option<Detection> {
+    let scores: Vec<(Language, usize)> = VOCABULARY
+        .iter()
+        .map(|(language, words)| {
+            (
+                *language,
+                words.iter().filter(|w| text.contains(*w)).count(),
+            )
+        })
+        .collect();
+    let total: usize = scores.iter().map(|(_, score)| score).sum();
+    debug!("language scores {scores:?}");
+    scores
+        .into_iter()
+        .max_by_key(|(_, score)| *score)
+        .filter(|_| total > 0)
+        .map(|(language, score)| Detection {
+            language,
+            confidence: score as f64 / total as f64,
+        })
+}
+
+/// detected language of the text, German when nothing is known, warns on unreliable results
+pub fn detect_or_default(filename: &str, text: &str) -> Language {
+    match detect(text) {
+        Some(d) => {
+            if d.confidence < LOW_CONFIDENCE {
+                warn!(
+                    "{filename}: language detection unsure, {:?} with confidence {:.2}, use --language to override",
+                    d.language, d.confidence
+                );
+            }
+            d.language
+        }
+        None => {
+            warn!("{filename}: no known vocabulary found, assuming German");
+            Language::German
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_language_with_confidence() {
+        let d = detect("Börsenabrechnung - Kauf\nVertrag\nValuta 01.02.2023").unwrap();
+        assert_eq!(d.language, Language::German);
+        assert_eq!(d.confidence, 1.0);
+        let d = detect("Opération de bourse - Achat\nContrat\nValuta 01.02.2023").unwrap();
+        assert_eq!(d.language, Language::French);
+        assert_eq!(d.confidence, 1.0);
+        let d = detect("Opération de bourse\nContrat\nVertrag").unwrap();
+        assert_eq!(d.language, Language::French);
+        assert!(d.confidence < LOW_CONFIDENCE);
+        assert!(detect("nothing to see").is_none());
+    }
+}
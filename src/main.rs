@@ -3,14 +3,33 @@ use log::{debug, error, info, warn};
 use std::collections::HashMap;
 use std::time::SystemTime;
 
+mod allocation;
+mod cash_ledger;
+mod classify;
+mod contributions;
+mod cost_basis;
+mod dividend_taxes;
 mod eurofxref;
+mod fees;
+mod fx_costs;
+mod holdings;
+mod language;
 mod money;
 mod options;
+mod passwords;
 mod pdf_text;
+mod performance;
+mod rebalance;
+mod reconcile;
+mod report;
+mod securities;
+mod template;
 mod viac_csv;
 mod viac_pdf;
 
-use viac_pdf::{ViacDocument, ViacPdf, ViacPdfExtractor, ViacSummary};
+use securities::Securities;
+use template::ExtractionTemplate;
+use viac_pdf::{OpenOptions, ViacDocument, ViacPdf, ViacSummary};
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
@@ -27,6 +46,12 @@ fn main() -> anyhow::Result<()> {
         let x = d.fetch("2023-03-21", [b'C', b'H', b'F'])?;
         dbg!(x);
     }
+    let passwords =
+        passwords::PdfPasswords::new(args.password.clone(), args.password_file.as_deref())?;
+    let templates = match args.templates {
+        Some(ref dir) => ExtractionTemplate::from_dir(dir)?,
+        None => vec![],
+    };
     let now = SystemTime::now();
 
     let entries = walkdir::WalkDir::new(&path).into_iter();
@@ -37,18 +62,16 @@ fn main() -> anyhow::Result<()> {
         .filter(|pfn| pfn.path().extension() == pdf_ext)
     {
         info!("{:?}", entry);
-        match ViacPdf::from_path(entry.path()) {
+        let options = OpenOptions {
+            password: passwords.get(entry.path()),
+            templates: &templates,
+            language: args.language,
+        };
+        match ViacPdf::from_path(entry.path(), &options) {
             Ok(vpdf) => {
-                let s = match vpdf {
-                    ViacPdf::French(p) => {
-                        p.print_summary();
-                        p.summary(args.deduce_amount)
-                    }
-                    ViacPdf::German(p) => {
-                        p.print_summary();
-                        p.summary(args.deduce_amount)
-                    }
-                };
+                let p = vpdf.extractor();
+                p.print_summary();
+                let s = p.summary(args.deduce_amount);
                 match s {
                     Ok(s) => {
                         match s.document_type {
@@ -67,20 +90,39 @@ fn main() -> anyhow::Result<()> {
                             ViacDocument::TaxReturn(_) => {
                                 debug!("{:?}", s);
                             }
-                            ViacDocument::FeesRefund(_)
-                            | ViacDocument::InterestCharge(_)
-                            | ViacDocument::Tax(_)
-                            | ViacDocument::TransferIn(_)
-                            | ViacDocument::TransferOut(_)
-                            | ViacDocument::DeliveryIn(_)
-                            | ViacDocument::DeliveryOut(_)
-                            | ViacDocument::Outgoing(_) => {
-                                unimplemented!();
+                            ViacDocument::DeliveryIn(ref d) | ViacDocument::DeliveryOut(ref d) => {
+                                debug!("{:?}", s);
+                                debug!("delivery value {}", d.value());
+                            }
+                            ViacDocument::TransferIn(ref t) | ViacDocument::TransferOut(ref t) => {
+                                debug!("{:?}", s);
+                                debug!("counterparty {:?}", t.counterparty);
+                            }
+                            ViacDocument::ContributionCertificate(_)
+                            | ViacDocument::TaxStatement(_) => {
+                                debug!("{:?}", s);
+                            }
+                            ViacDocument::AnnualStatement(ref a) => {
+                                debug!("{:?}", s);
+                                info!(
+                                    "annual statement {} with {} positions",
+                                    a.valuation_date,
+                                    a.holdings.len()
+                                );
+                                for h in &a.holdings {
+                                    debug!("{} {} {} shares", h.isin, h.share_title, h.shares);
+                                }
+                            }
+                            ViacDocument::FeesRefund(_) | ViacDocument::InterestCharge(_) => {
+                                debug!("{:?}", s);
                             }
                             ViacDocument::Purchase(ref t) | ViacDocument::Sale(ref t) => {
                                 debug!("{:?}", s);
-                                debug!("Valuta w/o taxes {:?}", &t.valuta_without_taxes());
+                                debug!("Valuta w/o costs {:?}", &t.valuta_without_costs());
                                 debug!("real shares {:?}", &t.real_shares_count().round_dp(7));
+                                if let Some(er) = s.exchange() {
+                                    debug!("converted {} to {}", er.foreign(), er.pdf_price);
+                                }
                             }
                             ViacDocument::NotViac => {
                                 warn!("PDF author is not Viac");
@@ -93,19 +135,33 @@ fn main() -> anyhow::Result<()> {
                         }
                         all_docs
                             .entry(s.portfolio_number.to_string())
-                            .or_insert_with(Vec::new)
+                            .or_default()
                             .push(s);
                     }
-                    Err(_) => {
-                        error!("ERROR pdf unreadable");
+                    Err(e) => {
+                        error!("ERROR pdf unreadable {}: {e}", entry.path().display());
                         continue;
                     }
                 }
             }
-            Err(e) => error!("pdf reading error {e:?}"),
+            Err(e) => error!("pdf reading error {}: {e}", entry.path().display()),
+        }
+    }
+    for summaries in all_docs.values_mut() {
+        holdings::rate_deliveries(summaries);
+        dividend_taxes::link_refunds(summaries);
+        rebalance::tag_trades(summaries);
+    }
+    match args.command {
+        Some(options::Command::Report(ref report_args)) => report::run(report_args, &all_docs)?,
+        None => {
+            let securities = match args.securities {
+                Some(ref path) => Securities::builtin().with_file(path)?,
+                None => Securities::builtin(),
+            };
+            viac_csv::write_summaries(all_docs, args.isin_currency.as_slice(), &securities)?
         }
     }
-    viac_csv::write_summaries(all_docs, args.isin_currency.as_slice())?;
 
     if let Ok(elapsed) = now.elapsed() {
         info!(
@@ -1,7 +1,11 @@
 //! command line options
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
 use std::{path::PathBuf, str::FromStr};
 use thiserror::Error;
 
+use crate::language::Language;
+
 #[derive(clap::Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub(crate) struct Cli {
@@ -17,6 +21,108 @@ pub(crate) struct Cli {
     /// format: AT3456789014,USD
     #[clap(short, long)]
     pub isin_currency: Vec<IsinCurrency>,
+    /// password used to open encrypted PDFs
+    #[clap(short, long)]
+    pub password: Option<String>,
+    /// file with one `path,password` line per encrypted PDF, path may be the filename only
+    #[clap(long)]
+    pub password_file: Option<PathBuf>,
+    /// directory with JSON extraction templates for document layouts not supported out of the box
+    #[clap(short, long)]
+    pub templates: Option<PathBuf>,
+    /// language of all PDFs, skips the detection by vocabulary
+    #[clap(short, long, value_enum)]
+    pub language: Option<Language>,
+    /// CSV with ticker, WKN and quote feed per ISIN for the shares export, overriding the builtin mapping
+    /// header: isin,ticker,wkn,exchange,provider,quote_currency
+    #[clap(long)]
+    pub securities: Option<PathBuf>,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum Command {
+    /// print reports instead of writing the import CSV files
+    Report(ReportArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct ReportArgs {
+    /// reports to print, holdings when none is given
+    #[clap(value_enum)]
+    pub kinds: Vec<ReportKind>,
+    /// date of the holdings and allocation reports, format: 2023-12-31, defaults to today
+    #[clap(long)]
+    pub at: Option<NaiveDate>,
+    /// differences in shares or cash up to this amount are ignored
+    #[clap(long, default_value = "0.001")]
+    pub tolerance: Decimal,
+    /// assign a VIAC contract (Vertrag) to a person, contracts without one count as their own person
+    /// format: 1234567,Anna
+    #[clap(long)]
+    pub person: Vec<ContractPerson>,
+    /// person, or contract number when not assigned with --person, allowed the higher 3a limit of the self-employed without pension fund
+    #[clap(long)]
+    pub self_employed: Vec<String>,
+    /// CSV file with yearly 3a limits replacing the builtin ones, format: year,employed,self_employed
+    #[clap(long)]
+    pub limits: Option<PathBuf>,
+    /// CSV file assigning ISINs to asset classes for the allocation report, format: isin,class
+    #[clap(long)]
+    pub asset_classes: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub(crate) enum ReportKind {
+    /// shares per ISIN and cash of each portfolio at --at, valued at the last execution price
+    Holdings,
+    /// compare the positions replayed from transactions with the annual statements
+    Reconcile,
+    /// sum the 3a deposits per person and year and check them against the yearly limit
+    ContributionLimits,
+    /// per ISIN gross dividends, withheld and refunded tax and the net withholding rate
+    DividendTaxes,
+    /// realized gains of sales and unrealized gains of positions, by moving average and FIFO
+    CostBasis,
+    /// yearly time-weighted and money-weighted returns, also written to <account>_Returns.csv
+    Performance,
+    /// running balance of the cash account, flags days ending below zero
+    CashLedger,
+    /// yearly management fees, stamp duty and FX costs relative to the average invested capital
+    Fees,
+    /// spread of the VIAC conversion rates against the ECB reference rates of eurofxref-hist.zip
+    FxCosts,
+    /// allocation by security, currency and asset class at --at, and the drift between trade events
+    Allocation,
+    /// same day trades grouped into events triggered by a deposit, a fee or a rebalance
+    Rebalancing,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContractPerson {
+    pub contract: String,
+    pub person: String,
+}
+
+impl FromStr for ContractPerson {
+    type Err = ContractPersonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(',') {
+            Some((contract, person)) if !person.is_empty() => Ok(Self {
+                contract: contract.trim().to_string(),
+                person: person.trim().to_string(),
+            }),
+            _ => Err(Self::Err::ContractAndPersonNotFound),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ContractPersonError {
+    #[error("comma separated contract and person not found")]
+    ContractAndPersonNotFound,
 }
 
 #[derive(Clone)]
@@ -69,7 +175,7 @@ impl std::fmt::Display for IsinCurrency {
 #[derive(Debug, Error)]
 pub enum IsinCurrencyError {
     #[error("ISIN parser failed: {0}")]
-    IsinError(isin::ISINError),
+    IsinError(isin::Error),
     #[error("currency code must be 3 chars long")]
     CurrencyNotThreeChar,
     #[error("comma separator not found")]
@@ -0,0 +1,78 @@
+//! passwords for encrypted VIAC PDFs
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Passwords handed to the PDF backend, either one for all files
+/// or looked up per file from a password file
+#[derive(Debug, Default)]
+pub struct PdfPasswords {
+    default: Option<String>,
+    per_file: HashMap<PathBuf, String>,
+}
+
+/// path and password trimmed, a password with surrounding spaces can't be given in the file
+fn read_password_file(rdr: impl std::io::Read) -> csv::Result<HashMap<PathBuf, String>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(rdr);
+    let mut per_file = HashMap::new();
+    for record in rdr.records() {
+        let record = record?;
+        if let (Some(path), Some(password)) = (record.get(0), record.get(1)) {
+            per_file.insert(PathBuf::from(path), password.to_string());
+        }
+    }
+    Ok(per_file)
+}
+
+impl PdfPasswords {
+    /// password file format, one file per line without header: path,password
+    /// the path may also be just the filename of the PDF
+    pub fn new(default: Option<String>, password_file: Option<&Path>) -> csv::Result<Self> {
+        let per_file = match password_file {
+            Some(password_file) => read_password_file(std::fs::File::open(password_file)?)?,
+            None => HashMap::new(),
+        };
+        Ok(Self { default, per_file })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&str> {
+        self.per_file
+            .get(path)
+            .or_else(|| {
+                path.file_name()
+                    .and_then(|f| self.per_file.get(Path::new(f)))
+            })
+            .or(self.default.as_ref())
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_prefers_path_then_filename_then_default() {
+        let file = "/pdfs/2023/a.pdf, full\nb.pdf, name only\n";
+        let passwords = PdfPasswords {
+            default: Some("default".to_string()),
+            per_file: read_password_file(file.as_bytes()).unwrap(),
+        };
+        assert_eq!(passwords.get(Path::new("/pdfs/2023/a.pdf")), Some("full"));
+        assert_eq!(
+            passwords.get(Path::new("/pdfs/2022/b.pdf")),
+            Some("name only")
+        );
+        assert_eq!(
+            passwords.get(Path::new("/pdfs/2022/a.pdf")),
+            Some("default")
+        );
+        let no_default = PdfPasswords {
+            default: None,
+            per_file: HashMap::new(),
+        };
+        assert_eq!(no_default.get(Path::new("c.pdf")), None);
+    }
+}
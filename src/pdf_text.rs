@@ -297,29 +297,23 @@ pub fn page_text(page: &Page, resolve: &impl Resolve) -> Result<String, PdfError
                     out.push('\t');
                 }
             }
+            // TODO: handle all tag, and props enum variants with text?
             Op::BeginMarkedContent {
                 ref tag,
-                ref properties,
-            } => {
-                // TODO: handle all tag, and props enum variants with text?
-                if tag == "Span" {
-                    if let Some(props) = properties {
-                        match props {
-                            Primitive::Dictionary(d) => {
-                                if let Some(Primitive::String(text)) = d.get("ActualText") {
-                                    text_state.font.decode(&text.data, &mut out)?;
-                                }
-                            }
-                            x => {
-                                return Err(PdfError::UnexpectedPrimitive {
-                                    expected: "Dictionary",
-                                    found: x.get_debug_name(),
-                                })
-                            }
-                        }
+                properties: Some(ref props),
+            } if tag == "Span" => match props {
+                Primitive::Dictionary(d) => {
+                    if let Some(Primitive::String(text)) = d.get("ActualText") {
+                        text_state.font.decode(&text.data, &mut out)?;
                     }
                 }
-            }
+                x => {
+                    return Err(PdfError::UnexpectedPrimitive {
+                        expected: "Dictionary",
+                        found: x.get_debug_name(),
+                    })
+                }
+            },
             _ => {}
         }
     }
@@ -0,0 +1,277 @@
+//! money-weighted (XIRR) and time-weighted returns per portfolio
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, NaiveDateTime};
+use log::warn;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::holdings::{delivery_chf_rate, last_prices, Holdings};
+use crate::report;
+use crate::viac_csv::account_name;
+use crate::viac_pdf::{midnight, ViacDocument, ViacSummary};
+
+/// CHF moved into (positive) or out of the portfolio from outside, everything else is return,
+/// deliveries count at their reference value, None for those without an exchange rate
+fn external_flow(summaries: &[ViacSummary], s: &ViacSummary) -> Option<Decimal> {
+    match &s.document_type {
+        ViacDocument::Incoming(_) | ViacDocument::TransferIn(_) | ViacDocument::TransferOut(_) => {
+            s.cash_flow().map(|m| m.amount)
+        }
+        ViacDocument::DeliveryIn(d) | ViacDocument::DeliveryOut(d) => {
+            let Some(rate) = delivery_chf_rate(summaries, s, d) else {
+                warn!(
+                    "{}: no exchange rate for the delivery valued {}, left out of the returns",
+                    s.comment,
+                    d.value()
+                );
+                return None;
+            };
+            let value = (d.value().amount * rate).round_dp(2);
+            match s.document_type {
+                ViacDocument::DeliveryIn(_) => Some(value),
+                _ => Some(-value),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Value in CHF after all documents up to `at`, taken from the annual statement of that day
+/// when there is one, otherwise the replayed positions at their last execution price
+pub(crate) fn value_at(summaries: &[ViacSummary], at: NaiveDateTime) -> Decimal {
+    let statement = summaries.iter().find_map(|s| match &s.document_type {
+        ViacDocument::AnnualStatement(a) if a.valuation_date == at => Some(a),
+        _ => None,
+    });
+    match statement {
+        Some(a) => a.holdings.iter().map(|h| h.value.amount).sum::<Decimal>() + a.cash.amount,
+        None => Holdings::replay(summaries, at).value(&last_prices(summaries, at)),
+    }
+}
+
+fn year_end(year: i32) -> NaiveDateTime {
+    midnight(year, 12, 31)
+}
+
+/// Annualized internal rate of return of dated cash flows, deposits negative and the final value positive.
+/// None when the flows do not change sign or no rate up to 1'000'000% solves them.
+pub fn xirr(flows: &[(NaiveDateTime, Decimal)]) -> Option<f64> {
+    let start = flows.iter().map(|(d, _)| *d).min()?;
+    let flows: Vec<(f64, f64)> = flows
+        .iter()
+        .map(|(d, a)| ((*d - start).num_days() as f64 / 365.0, a.to_f64().unwrap()))
+        .collect();
+    if !flows.iter().any(|(_, a)| *a > 0.0) || !flows.iter().any(|(_, a)| *a < 0.0) {
+        return None;
+    }
+    let npv = |rate: f64| -> f64 {
+        flows
+            .iter()
+            .map(|(years, amount)| amount / (1.0 + rate).powf(*years))
+            .sum()
+    };
+    let (mut low, mut high) = (-0.9999, 1.0);
+    while npv(low).signum() == npv(high).signum() {
+        high *= 2.0;
+        if high > 10_000.0 {
+            return None;
+        }
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if npv(mid).signum() == npv(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+/// Chains the growth of the sub-periods between external flows,
+/// each one as (value at its start including the flow, value at its end before the next flow)
+pub fn time_weighted(periods: &[(Decimal, Decimal)]) -> Option<f64> {
+    let mut growth = 1.0;
+    let mut any = false;
+    for (begin, end) in periods.iter().filter(|(begin, _)| *begin > Decimal::ZERO) {
+        growth *= (end / begin).to_f64().unwrap();
+        any = true;
+    }
+    any.then_some(growth - 1.0)
+}
+
+struct YearReturn {
+    year: i32,
+    start_value: Decimal,
+    net_flows: Decimal,
+    end_value: Decimal,
+    twr: Option<f64>,
+    irr: Option<f64>,
+}
+
+fn yearly_returns(summaries: &[ViacSummary]) -> Vec<YearReturn> {
+    let mut flows: BTreeMap<NaiveDateTime, Decimal> = BTreeMap::new();
+    for s in summaries {
+        if let Some(flow) = external_flow(summaries, s) {
+            *flows.entry(s.valuta_date()).or_default() += flow;
+        }
+    }
+    let (Some(first), Some(last)) = (
+        flows.keys().next().copied(),
+        summaries.iter().map(|s| s.valuta_date()).max(),
+    ) else {
+        return vec![];
+    };
+    let mut years = vec![];
+    for year in first.year()..=last.year() {
+        let start = (year > first.year()).then(|| year_end(year - 1));
+        let end = if year == last.year() {
+            last
+        } else {
+            year_end(year)
+        };
+        let start_value = start.map_or(Decimal::ZERO, |d| value_at(summaries, d));
+        let year_flows: Vec<(NaiveDateTime, Decimal)> = flows
+            .range(..=end)
+            .filter(|(d, _)| !matches!(start, Some(s) if **d <= s))
+            .map(|(d, f)| (*d, *f))
+            .collect();
+        let end_value = value_at(summaries, end);
+
+        let mut periods = vec![];
+        let mut begin = start_value;
+        for (date, flow) in &year_flows {
+            let value = value_at(summaries, *date);
+            periods.push((begin, value - flow));
+            begin = value;
+        }
+        periods.push((begin, end_value));
+
+        let mut cash_flows: Vec<(NaiveDateTime, Decimal)> =
+            year_flows.iter().map(|(d, f)| (*d, -f)).collect();
+        if let Some(start) = start {
+            cash_flows.push((start, -start_value));
+        }
+        cash_flows.push((end, end_value));
+        years.push(YearReturn {
+            year,
+            start_value,
+            net_flows: year_flows.iter().map(|(_, f)| f).sum(),
+            end_value,
+            twr: time_weighted(&periods),
+            irr: xirr(&cash_flows),
+        });
+    }
+    years
+}
+
+fn percent(rate: Option<f64>) -> String {
+    rate.map_or("".to_string(), |r| format!("{:.2}", r * 100.0))
+}
+
+/// prints the returns per portfolio and writes them to `<account>_Returns.csv`
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) -> std::io::Result<()> {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        let account_name = account_name(portfolio, summaries);
+        let years = yearly_returns(summaries);
+        println!("== returns of {account_name} ==");
+        let mut file = std::fs::File::create(format!("{account_name}_Returns.csv"))?;
+        let mut wtr = csv::Writer::from_writer(&mut file);
+        wtr.write_record([
+            "Jahr",
+            "Anfangswert",
+            "Nettoeinzahlungen",
+            "Endwert",
+            "TTWROR %",
+            "IZF %",
+        ])?;
+        for y in &years {
+            println!(
+                "{}: {} + {} -> {} CHF, time-weighted {}%, money-weighted {}%",
+                y.year,
+                y.start_value.round_dp(2),
+                y.net_flows.round_dp(2),
+                y.end_value.round_dp(2),
+                percent(y.twr),
+                percent(y.irr)
+            );
+            wtr.write_record(&[
+                y.year.to_string(),
+                y.start_value.round_dp(2).to_string(),
+                y.net_flows.round_dp(2).to_string(),
+                y.end_value.round_dp(2).to_string(),
+                percent(y.twr),
+                percent(y.irr),
+            ])?;
+        }
+        let Some(last) = summaries.iter().map(|s| s.valuta_date()).max() else {
+            continue;
+        };
+        let mut cash_flows: Vec<(NaiveDateTime, Decimal)> = summaries
+            .iter()
+            .filter_map(|s| external_flow(summaries, s).map(|f| (s.valuta_date(), -f)))
+            .collect();
+        cash_flows.push((last, value_at(summaries, last)));
+        println!(
+            "since start: money-weighted {}% p.a.",
+            percent(xirr(&cash_flows))
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{cash, delivery, foreign_trade, summary};
+
+    #[test]
+    fn usd_delivery_counts_in_chf() {
+        let docs = [
+            summary(ViacDocument::Incoming(cash(
+                midnight(2023, 1, 2),
+                "CHF 1'000.00",
+            ))),
+            summary(ViacDocument::Purchase(foreign_trade(
+                midnight(2023, 1, 3),
+                "IE00B5BMR087",
+                "10",
+                "USD 100.00",
+                "0.9",
+            ))),
+            summary(ViacDocument::DeliveryIn(delivery(
+                midnight(2023, 6, 1),
+                "IE00B5BMR087",
+                "5",
+                "USD 100.00",
+            ))),
+        ];
+        // the delivery brings 450 CHF of shares, the portfolio itself did not move
+        let years = yearly_returns(&docs);
+        assert_eq!(years[0].net_flows, Decimal::from(1450));
+        assert_eq!(years[0].end_value, Decimal::from(1450));
+        assert!(years[0].twr.unwrap().abs() < 1e-9);
+    }
+
+    #[test]
+    fn xirr_of_one_year() {
+        let flows = [
+            (midnight(2021, 1, 1), Decimal::from(-100)),
+            (midnight(2022, 1, 1), Decimal::from(110)),
+        ];
+        assert!((xirr(&flows).unwrap() - 0.10).abs() < 1e-9);
+        assert!(xirr(&flows[..1]).is_none());
+    }
+
+    #[test]
+    fn time_weighted_ignores_deposit_size() {
+        // +10% on 100, then 1000 deposited and +0% on 1110
+        let periods = [
+            (Decimal::ZERO, Decimal::ZERO),
+            (Decimal::from(100), Decimal::from(110)),
+            (Decimal::from(1110), Decimal::from(1110)),
+        ];
+        assert!((time_weighted(&periods).unwrap() - 0.10).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,175 @@
+//! same day trades of a portfolio grouped into rebalance events and what triggered them
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Duration, NaiveDateTime};
+use log::debug;
+use rust_decimal::Decimal;
+
+use crate::report;
+use crate::viac_pdf::{ViacDocument, ViacSummary};
+
+/// VIAC invests a deposit or pays a fee within a few days
+const TRIGGER_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// new money was invested
+    Deposit,
+    /// shares were sold to pay the fees
+    Fee,
+    /// the allocation drifted from its target
+    Rebalance,
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trigger::Deposit => write!(f, "deposit"),
+            Trigger::Fee => write!(f, "fee"),
+            Trigger::Rebalance => write!(f, "rebalance"),
+        }
+    }
+}
+
+/// all purchases and sales of a portfolio on one valuta date
+#[derive(Debug)]
+pub struct RebalanceEvent {
+    pub date: NaiveDateTime,
+    pub trigger: Trigger,
+    /// indices of the trades into the summaries
+    pub trades: Vec<usize>,
+}
+
+/// Deposits within the days before the trades, not yet invested by an earlier event, trigger them,
+/// sales only right after a fee pay it, anything else rebalances
+fn trigger(
+    date: NaiveDateTime,
+    previous: Option<NaiveDateTime>,
+    inflows: &[NaiveDateTime],
+    fees: &[NaiveDateTime],
+    only_sales: bool,
+) -> Trigger {
+    let window = |d: &&NaiveDateTime| {
+        **d <= date
+            && **d >= date - Duration::days(TRIGGER_DAYS)
+            && !matches!(previous, Some(p) if **d <= p)
+    };
+    if inflows.iter().any(|d| window(&d)) {
+        Trigger::Deposit
+    } else if only_sales && fees.iter().any(|d| window(&d)) {
+        Trigger::Fee
+    } else {
+        Trigger::Rebalance
+    }
+}
+
+pub fn events(summaries: &[ViacSummary]) -> Vec<RebalanceEvent> {
+    let mut by_date: BTreeMap<NaiveDateTime, Vec<usize>> = BTreeMap::new();
+    let mut inflows = vec![];
+    let mut fees = vec![];
+    for (i, s) in summaries.iter().enumerate() {
+        match s.document_type {
+            ViacDocument::Purchase(_) | ViacDocument::Sale(_) => {
+                by_date.entry(s.valuta_date()).or_default().push(i)
+            }
+            ViacDocument::Incoming(_) | ViacDocument::TransferIn(_) => {
+                inflows.push(s.valuta_date())
+            }
+            ViacDocument::Fees(_) => fees.push(s.valuta_date()),
+            _ => {}
+        }
+    }
+    let mut previous = None;
+    by_date
+        .into_iter()
+        .map(|(date, trades)| {
+            let only_sales = trades
+                .iter()
+                .all(|i| matches!(summaries[*i].document_type, ViacDocument::Sale(_)));
+            let trigger = trigger(date, previous, &inflows, &fees, only_sales);
+            previous = Some(date);
+            RebalanceEvent {
+                date,
+                trigger,
+                trades,
+            }
+        })
+        .collect()
+}
+
+/// notes the trigger of its event in the comment of each trade
+pub fn tag_trades(summaries: &mut [ViacSummary]) {
+    for event in events(summaries) {
+        debug!(
+            "{}: {} trades triggered by {}",
+            event.date.date(),
+            event.trades.len(),
+            event.trigger
+        );
+        for i in event.trades {
+            summaries[i].comment += &format!(" ({})", event.trigger);
+        }
+    }
+}
+
+/// per portfolio every event with its trigger, the amounts bought and sold
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>) {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        println!("== trade events of portfolio {portfolio} ==");
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for event in events(summaries) {
+            let (mut bought, mut sold) = (Decimal::ZERO, Decimal::ZERO);
+            for i in &event.trades {
+                match summaries[*i].cash_flow() {
+                    Some(flow) if flow.amount < Decimal::ZERO => bought -= flow.amount,
+                    Some(flow) => sold += flow.amount,
+                    None => {}
+                }
+            }
+            println!(
+                "{} {}: {} trades, bought {} CHF sold {} CHF",
+                event.date.date(),
+                event.trigger,
+                event.trades.len(),
+                bought,
+                sold
+            );
+            *counts.entry(event.trigger.to_string()).or_default() += 1;
+        }
+        for (trigger, count) in counts {
+            println!("{count} events triggered by {trigger}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::midnight;
+
+    fn day(d: u32) -> NaiveDateTime {
+        midnight(2023, 3, d)
+    }
+
+    #[test]
+    fn deposit_triggers_only_the_next_event() {
+        let inflows = [day(1)];
+        let fees = [day(20)];
+        assert_eq!(
+            trigger(day(3), None, &inflows, &fees, false),
+            Trigger::Deposit
+        );
+        assert_eq!(
+            trigger(day(4), Some(day(3)), &inflows, &fees, false),
+            Trigger::Rebalance
+        );
+        assert_eq!(
+            trigger(day(21), Some(day(4)), &inflows, &fees, true),
+            Trigger::Fee
+        );
+        assert_eq!(
+            trigger(day(21), Some(day(4)), &inflows, &fees, false),
+            Trigger::Rebalance
+        );
+    }
+}
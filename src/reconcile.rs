@@ -0,0 +1,147 @@
+//! reconciliation of replayed positions against the VIAC annual statements
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{Duration, NaiveDateTime};
+use rust_decimal::Decimal;
+
+use crate::holdings::Holdings;
+use crate::report;
+use crate::viac_pdf::{ViacAnnualStatement, ViacDocument, ViacSummary};
+
+/// VIAC sends documents at least monthly, longer silences hint at missing PDFs
+const MAX_GAP_DAYS: i64 = 45;
+
+pub fn report(all_docs: &HashMap<String, Vec<ViacSummary>>, tolerance: Decimal) {
+    for (portfolio, summaries) in report::portfolios(all_docs) {
+        let mut statements: Vec<&ViacSummary> = summaries
+            .iter()
+            .filter(|s| matches!(s.document_type, ViacDocument::AnnualStatement(_)))
+            .collect();
+        statements.sort_by_key(|s| s.valuta_date());
+        println!("== portfolio {portfolio} ==");
+        if statements.is_empty() {
+            println!("no annual statements found, nothing to reconcile");
+        }
+        let mut period_start = summaries.iter().map(|s| s.valuta_date()).min();
+        for statement in statements {
+            let ViacDocument::AnnualStatement(ref a) = statement.document_type else {
+                unreachable!();
+            };
+            let replayed = Holdings::replay(summaries, a.valuation_date);
+            println!("statement {}", a.valuation_date.date());
+            let differences = differences(&replayed, a, tolerance);
+            for (what, computed, stated) in &differences {
+                println!(
+                    "  {what}: replayed {computed} stated {stated} difference {}",
+                    computed - stated
+                );
+            }
+            if differences.is_empty() {
+                println!("  ok");
+            } else if let Some(start) = period_start {
+                for (from, to) in gaps(summaries, start, a.valuation_date) {
+                    println!(
+                        "  no PDFs between {} and {}, likely missing",
+                        from.date(),
+                        to.date()
+                    );
+                }
+            }
+            period_start = Some(a.valuation_date);
+        }
+    }
+}
+
+/// ISINs and cash whose replayed amount differs from the statement by more than `tolerance`,
+/// as (ISIN or `cash`, replayed, stated)
+fn differences(
+    replayed: &Holdings,
+    statement: &ViacAnnualStatement,
+    tolerance: Decimal,
+) -> Vec<(String, Decimal, Decimal)> {
+    let mut isins: BTreeSet<&str> = replayed.shares.keys().map(String::as_str).collect();
+    isins.extend(statement.holdings.iter().map(|h| h.isin.as_str()));
+    let mut differences: Vec<(String, Decimal, Decimal)> = isins
+        .into_iter()
+        .map(|isin| {
+            let computed = replayed.shares.get(isin).copied().unwrap_or_default();
+            let stated = statement
+                .holdings
+                .iter()
+                .find(|h| h.isin == isin)
+                .map(|h| h.shares)
+                .unwrap_or_default();
+            (isin.to_string(), computed, stated)
+        })
+        .collect();
+    differences.push(("cash".to_string(), replayed.cash, statement.cash.amount));
+    differences.retain(|(_, computed, stated)| (computed - stated).abs() > tolerance);
+    differences
+}
+
+/// periods longer than MAX_GAP_DAYS without any document
+fn gaps(
+    summaries: &[ViacSummary],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let mut dates: Vec<NaiveDateTime> = summaries
+        .iter()
+        .map(|s| s.valuta_date())
+        .filter(|d| *d >= from && *d <= to)
+        .collect();
+    dates.push(from);
+    dates.push(to);
+    dates.sort();
+    dates
+        .windows(2)
+        .filter(|w| w[1] - w[0] > Duration::days(MAX_GAP_DAYS))
+        .map(|w| (w[0], w[1]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::*;
+    use crate::viac_pdf::midnight;
+
+    #[test]
+    fn statement_differences_and_gaps_are_found() {
+        let docs = [
+            summary(ViacDocument::Incoming(cash(
+                midnight(2023, 1, 5),
+                "CHF 1000.00",
+            ))),
+            summary(ViacDocument::Purchase(trade(
+                midnight(2023, 1, 6),
+                "CH0000000001",
+                "9",
+                "CHF 100.00",
+            ))),
+            summary(ViacDocument::Purchase(trade(
+                midnight(2023, 12, 1),
+                "CH0000000002",
+                "1",
+                "CHF 50.00",
+            ))),
+        ];
+        let statement = statement(
+            midnight(2023, 12, 31),
+            &[("CH0000000001", "10", "CHF 101.00")],
+            "CHF 50.00",
+        );
+        let replayed = Holdings::replay(&docs, statement.valuation_date);
+        let found = differences(&replayed, &statement, Decimal::new(1, 3));
+        let found: Vec<String> = found
+            .iter()
+            .map(|(what, computed, stated)| format!("{what} {computed} {stated}"))
+            .collect();
+        // the cash of 1000 - 900 - 50 agrees
+        assert_eq!(found, ["CH0000000001 9 10", "CH0000000002 1 0"]);
+        assert_eq!(
+            gaps(&docs, midnight(2023, 1, 1), statement.valuation_date),
+            vec![(midnight(2023, 1, 6), midnight(2023, 12, 1))]
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! reports printed instead of writing the import CSV files
+use std::collections::HashMap;
+
+use chrono::Local;
+
+use crate::allocation::{self, AssetClasses};
+use crate::cash_ledger;
+use crate::contributions;
+use crate::cost_basis;
+use crate::dividend_taxes;
+use crate::fees;
+use crate::fx_costs;
+use crate::holdings;
+use crate::options::{ReportArgs, ReportKind};
+use crate::performance;
+use crate::rebalance;
+use crate::reconcile;
+use crate::viac_pdf::ViacSummary;
+
+pub(crate) fn run(
+    args: &ReportArgs,
+    all_docs: &HashMap<String, Vec<ViacSummary>>,
+) -> anyhow::Result<()> {
+    let kinds = if args.kinds.is_empty() {
+        &[ReportKind::Holdings][..]
+    } else {
+        &args.kinds[..]
+    };
+    let at = args
+        .at
+        .unwrap_or_else(|| Local::now().date_naive())
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    for kind in kinds {
+        match kind {
+            ReportKind::Holdings => holdings::report(all_docs, at),
+            ReportKind::Reconcile => reconcile::report(all_docs, args.tolerance),
+            ReportKind::ContributionLimits => contributions::report(all_docs, args),
+            ReportKind::DividendTaxes => dividend_taxes::report(all_docs),
+            ReportKind::CostBasis => cost_basis::report(all_docs),
+            ReportKind::Performance => performance::report(all_docs)?,
+            ReportKind::CashLedger => cash_ledger::report(all_docs),
+            ReportKind::Fees => fees::report(all_docs),
+            ReportKind::FxCosts => fx_costs::report(all_docs)?,
+            ReportKind::Allocation => {
+                let classes = match &args.asset_classes {
+                    Some(path) => AssetClasses::from_file(path)?,
+                    None => AssetClasses::default(),
+                };
+                allocation::report(all_docs, at, &classes)
+            }
+            ReportKind::Rebalancing => rebalance::report(all_docs),
+        }
+    }
+    Ok(())
+}
+
+/// the portfolios ordered by number, each with its documents,
+/// without the tax documents of contracts, which name no portfolio
+pub(crate) fn portfolios(
+    all_docs: &HashMap<String, Vec<ViacSummary>>,
+) -> impl Iterator<Item = (&String, &Vec<ViacSummary>)> {
+    let mut portfolios: Vec<_> = all_docs
+        .iter()
+        .filter(|(portfolio, _)| !portfolio.is_empty())
+        .collect();
+    portfolios.sort_by_key(|(portfolio, _)| *portfolio);
+    portfolios.into_iter()
+}
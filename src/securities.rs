@@ -0,0 +1,78 @@
+//! ticker, WKN and quote feed of the securities, to let PP fetch their prices
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// mapping shipped for the ETFs VIAC uses, the index funds of the foundations have no public quotes
+const BUILTIN: &str = include_str!("../securities/viac_default.csv");
+
+/// one line of a securities file, all columns but the ISIN may be empty
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecurityMetadata {
+    pub isin: String,
+    /// symbol as the quote provider expects it, e.g. `CSSPX.SW` for Yahoo
+    #[serde(default)]
+    pub ticker: String,
+    #[serde(default)]
+    pub wkn: String,
+    #[serde(default)]
+    pub exchange: String,
+    /// PP quote feed to configure, e.g. YAHOO
+    #[serde(default)]
+    pub provider: String,
+    /// currency the quotes are in, when it differs from the trade currency
+    #[serde(default)]
+    pub quote_currency: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Securities(HashMap<String, SecurityMetadata>);
+
+impl Securities {
+    fn from_reader(rdr: impl std::io::Read) -> csv::Result<Self> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(rdr);
+        let mut securities = HashMap::new();
+        for record in rdr.deserialize() {
+            let s: SecurityMetadata = record?;
+            securities.insert(s.isin.clone(), s);
+        }
+        Ok(Self(securities))
+    }
+
+    pub fn builtin() -> Self {
+        Self::from_reader(BUILTIN.as_bytes()).unwrap()
+    }
+
+    /// CSV with header: isin,ticker,wkn,exchange,provider,quote_currency
+    /// its lines replace the builtin ones of the same ISIN
+    pub fn with_file(mut self, path: &Path) -> csv::Result<Self> {
+        let file = Self::from_reader(std::fs::File::open(path)?)?;
+        self.0.extend(file.0);
+        Ok(self)
+    }
+
+    pub fn get(&self, isin: &str) -> Option<&SecurityMetadata> {
+        self.0.get(isin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_mapping_parses() {
+        let securities = Securities::builtin();
+        let sp500 = securities.get("IE00B5BMR087").unwrap();
+        assert_eq!(sp500.ticker, "CSSPX.SW");
+        assert_eq!(sp500.quote_currency, "USD");
+        // the USD line of the London listing, Amsterdam quotes it in EUR
+        let world = securities.get("IE00B4L5Y983").unwrap();
+        assert_eq!(world.ticker, "IWDA.L");
+        assert_eq!(world.quote_currency, "USD");
+        assert!(securities.get("CH0000000000").is_none());
+    }
+}
@@ -0,0 +1,463 @@
+//! user supplied extraction templates for VIAC document layouts the builtin extractors do not know
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use log::info;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use crate::classify::{contains_marker, DocumentKind, Rule};
+use crate::money::Money;
+use crate::viac_pdf::CostKind;
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("reading template {0} failed: {1}")]
+    Io(String, std::io::Error),
+    #[error("template {0} is invalid: {1}")]
+    Json(String, serde_json::Error),
+    #[error("template {0}: rule {1:?} is {2:?}, which templates can't extract")]
+    UnsupportedKind(String, String, DocumentKind),
+    #[error("template {0}: rule {1:?} needs the field {2}")]
+    MissingField(String, String, &'static str),
+    #[error("template {0}: exchange_total needs exchange_rate")]
+    MissingExchangeRate(String),
+    #[error("template {0:?}: field {1} not found")]
+    NotFound(String, &'static str),
+    #[error("template {0:?}: field {1} can't be parsed: {2}")]
+    Unparsable(String, &'static str, String),
+}
+
+/// A regex applied to the text of the first page,
+/// the named groups it needs to provide depend on the field
+#[derive(Debug)]
+pub struct Pattern(Regex);
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Regex::new(&s)
+            .map(Pattern)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// date regex with group `date` and the chrono format to parse it
+#[derive(Debug, Deserialize)]
+pub struct DatePattern {
+    pub regex: Pattern,
+    pub format: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CostPattern {
+    pub kind: CostKind,
+    pub pattern: Pattern,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateRule {
+    pub name: String,
+    pub markers: Vec<String>,
+    #[serde(default)]
+    pub exclusions: Vec<String>,
+    pub kind: DocumentKind,
+}
+
+impl Rule for TemplateRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> DocumentKind {
+        self.kind
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        self.markers.iter().all(|m| contains_marker(text, m))
+            && !self.exclusions.iter().any(|e| contains_marker(text, e))
+    }
+}
+
+/// Money patterns provide the groups `currency` and `amount`,
+/// text patterns the group `value`, the exchange rate pattern the group `rate`
+#[derive(Debug, Deserialize)]
+pub struct TemplateFields {
+    pub account: Pattern,
+    pub portfolio: Pattern,
+    pub date: DatePattern,
+    pub interest_date: Option<DatePattern>,
+    pub ex_date: Option<DatePattern>,
+    pub isin: Option<Pattern>,
+    pub share_title: Option<Pattern>,
+    pub shares: Option<Pattern>,
+    pub price: Option<Pattern>,
+    pub total: Option<Pattern>,
+    pub valuta: Pattern,
+    /// stamp duty of a trade
+    pub taxes: Option<Pattern>,
+    /// further cost lines of a trade
+    #[serde(default)]
+    pub costs: Vec<CostPattern>,
+    pub exchange_rate: Option<Pattern>,
+    /// amount in CHF after conversion, next to the exchange rate
+    pub exchange_total: Option<Pattern>,
+    pub dividend: Option<Pattern>,
+    /// foreign tax deducted from a dividend
+    pub withholding_tax: Option<Pattern>,
+    pub interest: Option<Pattern>,
+    /// portfolio or foundation on the other side of a transfer
+    pub counterparty: Option<Pattern>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateFile {
+    name: String,
+    detect: Vec<String>,
+    #[serde(default)]
+    vested_benefits: Vec<String>,
+    #[serde(default)]
+    pillar_3a: Vec<String>,
+    rules: Vec<TemplateRule>,
+    fields: TemplateFields,
+}
+
+#[derive(Debug)]
+pub struct ExtractionTemplate {
+    pub name: String,
+    /// all phrases must be on the first page for the template to be used
+    pub detect: Vec<String>,
+    /// phrases marking a vested benefits or a pillar 3a portfolio
+    pub vested_benefits: Vec<String>,
+    pub pillar_3a: Vec<String>,
+    pub rules: Vec<TemplateRule>,
+    pub fields: TemplateFields,
+}
+
+impl ExtractionTemplate {
+    pub fn from_file(path: &Path) -> Result<Self, TemplateError> {
+        let name = path.display().to_string();
+        let content =
+            std::fs::read_to_string(path).map_err(|e| TemplateError::Io(name.clone(), e))?;
+        Self::from_json(name, &content)
+    }
+
+    /// rejects rules the extractor can't serve: statements, or kinds whose fields are left out
+    fn from_json(name: String, content: &str) -> Result<Self, TemplateError> {
+        let file: TemplateFile =
+            serde_json::from_str(content).map_err(|e| TemplateError::Json(name.clone(), e))?;
+        for rule in &file.rules {
+            let fields = fields_read(rule.kind).ok_or_else(|| {
+                TemplateError::UnsupportedKind(name.clone(), rule.name.clone(), rule.kind)
+            })?;
+            if let Some(field) = fields
+                .required
+                .iter()
+                .find(|f| file.fields.patterns(f).is_empty())
+            {
+                return Err(TemplateError::MissingField(name, rule.name.clone(), field));
+            }
+        }
+        if file.fields.exchange_total.is_some() && file.fields.exchange_rate.is_none() {
+            return Err(TemplateError::MissingExchangeRate(name));
+        }
+        Ok(Self {
+            name: file.name,
+            detect: file.detect,
+            vested_benefits: file.vested_benefits,
+            pillar_3a: file.pillar_3a,
+            rules: file.rules,
+            fields: file.fields,
+        })
+    }
+
+    /// loads all `*.json` templates of the directory
+    pub fn from_dir(dir: &Path) -> Result<Vec<Rc<Self>>, TemplateError> {
+        let name = dir.display().to_string();
+        let mut templates = vec![];
+        for entry in std::fs::read_dir(dir).map_err(|e| TemplateError::Io(name.clone(), e))? {
+            let path = entry
+                .map_err(|e| TemplateError::Io(name.clone(), e))?
+                .path();
+            if path.extension() == Some(std::ffi::OsStr::new("json")) {
+                let template = Self::from_file(&path)?;
+                info!(
+                    "loaded template {:?} from {}",
+                    template.name,
+                    path.display()
+                );
+                templates.push(Rc::new(template));
+            }
+        }
+        Ok(templates)
+    }
+
+    pub fn detects(&self, text: &str) -> bool {
+        self.detect.iter().all(|d| text.contains(d.as_str()))
+    }
+
+    /// Checks before extraction that the fields a document of `kind` needs are in the text
+    /// and that all fields it reads parse, the extractor then only reads checked fields
+    pub fn check(&self, kind: DocumentKind, text: &str) -> Result<(), TemplateError> {
+        let Some(fields) = fields_read(kind) else {
+            return Err(TemplateError::UnsupportedKind(
+                self.name.clone(),
+                String::new(),
+                kind,
+            ));
+        };
+        let found = |field: &'static str| -> Result<bool, TemplateError> {
+            let mut found = false;
+            for pattern in self.fields.patterns(field) {
+                found |= pattern
+                    .probe(text)
+                    .map_err(|e| TemplateError::Unparsable(self.name.clone(), field, e))?;
+            }
+            Ok(found)
+        };
+        for field in fields.required {
+            if !found(field)? {
+                return Err(TemplateError::NotFound(self.name.clone(), field));
+            }
+        }
+        for field in fields.optional {
+            found(field)?;
+        }
+        if fields.optional.contains(&"exchange_total")
+            && found("exchange_total")?
+            && !found("exchange_rate")?
+        {
+            return Err(TemplateError::NotFound(self.name.clone(), "exchange_rate"));
+        }
+        Ok(())
+    }
+}
+
+/// the fields read for a kind of document, as named in the template file
+struct FieldsRead {
+    required: &'static [&'static str],
+    optional: &'static [&'static str],
+}
+
+/// None for the statements, their positions and totals are beyond a single regex
+fn fields_read(kind: DocumentKind) -> Option<FieldsRead> {
+    use DocumentKind::*;
+    let (required, optional): (&'static [&'static str], &'static [&'static str]) = match kind {
+        Purchase | Sale => (
+            &[
+                "date",
+                "valuta",
+                "isin",
+                "share_title",
+                "shares",
+                "price",
+                "total",
+            ],
+            &["taxes", "costs", "exchange_rate", "exchange_total"],
+        ),
+        Dividend | TaxReturn => (
+            &[
+                "date",
+                "valuta",
+                "isin",
+                "share_title",
+                "shares",
+                "dividend",
+                "total",
+            ],
+            &[
+                "withholding_tax",
+                "ex_date",
+                "exchange_rate",
+                "exchange_total",
+            ],
+        ),
+        Interest => (&["interest_date", "interest"], &[]),
+        Fees | FeesRefund | InterestCharge | Incoming => (&["date", "valuta"], &[]),
+        TransferIn | TransferOut => (&["date", "valuta"], &["counterparty"]),
+        DeliveryIn | DeliveryOut => (&["date", "isin", "share_title", "shares", "price"], &[]),
+        DividendCorrection => (&[], &[]),
+        AnnualStatement | ContributionCertificate | TaxStatement => return None,
+    };
+    Some(FieldsRead { required, optional })
+}
+
+/// a field's pattern together with how its captures are parsed
+enum FieldPattern<'a> {
+    Text(&'a Pattern),
+    Decimal(&'a Pattern, &'static str),
+    Money(&'a Pattern),
+    Date(&'a DatePattern),
+}
+
+impl FieldPattern<'_> {
+    /// whether the field is in the text, an error when it is but doesn't parse
+    fn probe(&self, text: &str) -> Result<bool, String> {
+        Ok(match self {
+            FieldPattern::Text(p) => p.text(text).is_some(),
+            FieldPattern::Decimal(p, group) => p.try_decimal(text, group)?.is_some(),
+            FieldPattern::Money(p) => p.try_money(text)?.is_some(),
+            FieldPattern::Date(p) => p.try_date(text)?.is_some(),
+        })
+    }
+}
+
+impl TemplateFields {
+    /// the patterns of a field by its name in the template file, empty when it is left out
+    fn patterns(&self, field: &str) -> Vec<FieldPattern<'_>> {
+        use FieldPattern::*;
+        match field {
+            "account" => vec![Text(&self.account)],
+            "portfolio" => vec![Text(&self.portfolio)],
+            "date" => vec![Date(&self.date)],
+            // the extractor falls back to the date
+            "interest_date" => vec![Date(self.interest_date.as_ref().unwrap_or(&self.date))],
+            "ex_date" => self.ex_date.iter().map(Date).collect(),
+            "isin" => self.isin.iter().map(Text).collect(),
+            "share_title" => self.share_title.iter().map(Text).collect(),
+            "counterparty" => self.counterparty.iter().map(Text).collect(),
+            "shares" => self.shares.iter().map(|p| Decimal(p, "value")).collect(),
+            "exchange_rate" => self
+                .exchange_rate
+                .iter()
+                .map(|p| Decimal(p, "rate"))
+                .collect(),
+            "price" => self.price.iter().map(Money).collect(),
+            "total" => self.total.iter().map(Money).collect(),
+            "valuta" => vec![Money(&self.valuta)],
+            "taxes" => self.taxes.iter().map(Money).collect(),
+            "costs" => self.costs.iter().map(|c| Money(&c.pattern)).collect(),
+            "exchange_total" => self.exchange_total.iter().map(Money).collect(),
+            "dividend" => self.dividend.iter().map(Money).collect(),
+            "withholding_tax" => self.withholding_tax.iter().map(Money).collect(),
+            "interest" => self.interest.iter().map(Money).collect(),
+            _ => unreachable!("unknown template field {field}"),
+        }
+    }
+}
+
+impl Pattern {
+    pub fn text(&self, text: &str) -> Option<String> {
+        self.0
+            .captures(text)
+            .and_then(|c| c.name("value"))
+            .map(|m| m.as_str().trim().to_string())
+    }
+
+    /// None when the pattern doesn't match or the capture doesn't parse,
+    /// `ExtractionTemplate::check` reports the latter
+    pub fn decimal(&self, text: &str, group: &str) -> Option<Decimal> {
+        self.try_decimal(text, group).ok().flatten()
+    }
+
+    pub fn money(&self, text: &str) -> Option<Money> {
+        self.try_money(text).ok().flatten()
+    }
+
+    fn try_decimal(&self, text: &str, group: &str) -> Result<Option<Decimal>, String> {
+        self.0
+            .captures(text)
+            .and_then(|c| c.name(group))
+            .map(|m| parse_decimal(m.as_str()))
+            .transpose()
+    }
+
+    fn try_money(&self, text: &str) -> Result<Option<Money>, String> {
+        let Some(c) = self.0.captures(text) else {
+            return Ok(None);
+        };
+        match (c.name("currency"), c.name("amount")) {
+            (Some(currency), Some(amount)) if currency.as_str().len() == 3 => Ok(Some(Money::new(
+                currency.as_str(),
+                parse_decimal(amount.as_str())?,
+            ))),
+            (Some(currency), Some(_)) => Err(format!("currency {:?}", currency.as_str())),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn parse_decimal(s: &str) -> Result<Decimal, String> {
+    Decimal::from_str(&s.replace('\'', "")).map_err(|e| format!("{s:?} {e}"))
+}
+
+impl DatePattern {
+    pub fn date(&self, text: &str) -> Option<NaiveDateTime> {
+        self.try_date(text).ok().flatten()
+    }
+
+    fn try_date(&self, text: &str) -> Result<Option<NaiveDateTime>, String> {
+        self.regex
+            .0
+            .captures(text)
+            .and_then(|c| c.name("date"))
+            .map(|m| {
+                NaiveDate::parse_from_str(m.as_str(), &self.format)
+                    .map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+                    .map_err(|e| format!("{:?} {e}", m.as_str()))
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_template_extracts_fields() {
+        let t = ExtractionTemplate::from_file(Path::new("templates/viac_de_example.json")).unwrap();
+        let page = "Börsenabrechnung - Kauf\nVertrag\n1234\nPortfolio\n5678\n\
+                    3.141\nAnt\nCSIF World\nISIN: \nCH0000000001\nKurs:\nUSD 1'234.50\n\
+                    Valuta 01.02.2023\nCHF\n3'878.00";
+        assert!(t.detects(page));
+        assert_eq!(t.rules[0].kind, DocumentKind::Purchase);
+        assert_eq!(t.fields.portfolio.text(page).unwrap(), "5678");
+        assert_eq!(
+            t.fields.isin.as_ref().unwrap().text(page).unwrap(),
+            "CH0000000001"
+        );
+        let price = t.fields.price.as_ref().unwrap().money(page).unwrap();
+        assert_eq!(price.amount, Decimal::from_str("1234.50").unwrap());
+        assert_eq!(
+            t.fields.valuta.money(page).unwrap().amount.to_string(),
+            "3878.00"
+        );
+        let date = t.fields.date.date(page).unwrap();
+        assert_eq!(date.to_string(), "2023-02-01 00:00:00");
+        assert!(matches!(
+            t.check(DocumentKind::Purchase, page),
+            Err(TemplateError::NotFound(_, "total"))
+        ));
+        let page = format!("{page}\nBetrag\nCHF\n-3'878.00");
+        assert!(t.check(DocumentKind::Purchase, &page).is_ok());
+        let page = page.replace("Valuta 01.02.2023", "Valuta 41.02.2023");
+        assert!(matches!(
+            t.check(DocumentKind::Purchase, &page),
+            Err(TemplateError::Unparsable(_, "date", _))
+        ));
+    }
+
+    #[test]
+    fn rules_the_fields_cannot_serve_are_rejected() {
+        let example = std::fs::read_to_string("templates/viac_de_example.json").unwrap();
+        let with_rule = |kind: &str| {
+            let mut json: serde_json::Value = serde_json::from_str(&example).unwrap();
+            json["rules"] = serde_json::json!([{ "name": "r", "markers": ["x"], "kind": kind }]);
+            ExtractionTemplate::from_json("t".to_string(), &json.to_string())
+        };
+        assert!(with_rule("Sale").is_ok());
+        assert!(matches!(
+            with_rule("Dividend"),
+            Err(TemplateError::MissingField(_, _, "dividend"))
+        ));
+        assert!(matches!(
+            with_rule("AnnualStatement"),
+            Err(TemplateError::UnsupportedKind(..))
+        ));
+    }
+}
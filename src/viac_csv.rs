@@ -1,7 +1,10 @@
+use crate::money::Money;
 use crate::options::IsinCurrency;
-use crate::viac_pdf::{ViacDocument, ViacSummary};
+use crate::securities::Securities;
+use crate::viac_pdf::{ProductType, ViacDocument, ViacSummary};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 struct ShareInfo {
     isin: String,
@@ -13,6 +16,7 @@ struct ShareInfo {
 pub fn write_summaries(
     viac_summaries: HashMap<String, Vec<ViacSummary>>,
     isin_currency: &[IsinCurrency],
+    securities: &Securities,
 ) -> std::io::Result<()> {
     // first write out all shares
     let mut all_shares: HashMap<String, ShareInfo> = HashMap::new();
@@ -36,12 +40,7 @@ pub fn write_summaries(
         // later we fake the currency for dividends
         summaries
             .iter()
-            .filter(|s| {
-                matches!(
-                    s.document_type,
-                    ViacDocument::Purchase(_) | ViacDocument::Sale(_)
-                )
-            })
+            .filter(|s| s.is_portfolio_transaction())
             .for_each(|s| {
                 let isin = s.isin();
                 if !isin.is_empty() {
@@ -55,16 +54,30 @@ pub fn write_summaries(
                 }
             });
     }
+    for v in all_shares.values_mut() {
+        let Some(meta) = securities.get(&v.isin) else {
+            continue;
+        };
+        if !meta.provider.is_empty() {
+            v.comment += &format!(" quotes: {} {}", meta.provider, meta.exchange);
+        }
+        // only noted, converting the currency is left to --isin-currency
+        if !meta.quote_currency.is_empty() && meta.quote_currency != v.currency {
+            v.comment += &format!(" quoted in {}", meta.quote_currency);
+        }
+    }
     for v in all_shares.values() {
+        let meta = securities.get(&v.isin);
         wtr.write_record(&[
-            v.isin.to_string(),     // "ISIN",
-            "".to_string(),         // "WKN",
-            "".to_string(),         //"Ticker-Symbol",
-            v.name.to_string(),     //"Wertpapiername",
-            v.currency.to_string(), //"Währung",
-            v.comment.to_string(),  // "Notiz",
+            v.isin.to_string(),                                // "ISIN",
+            meta.map_or("", |m| m.wkn.as_str()).to_owned(),    // "WKN",
+            meta.map_or("", |m| m.ticker.as_str()).to_owned(), //"Ticker-Symbol",
+            v.name.to_string(),                                //"Wertpapiername",
+            v.currency.to_string(),                            //"Währung",
+            v.comment.to_string(),                             // "Notiz",
         ])?;
     }
+    write_price_history(&viac_summaries, &isin_currency)?;
     // now all transactions
     let header = &[
         "Datum",
@@ -79,20 +92,46 @@ pub fn write_summaries(
         "Stück",
         "ISIN",
         "Notiz",
+        "Konto",
+        "Gegenkonto",
     ];
-    let forex = crate::eurofxref::EURO_FOREX.lock().unwrap();
+    let account_names: HashMap<String, String> = viac_summaries
+        .iter()
+        .map(|(portfolio, summaries)| (portfolio.to_owned(), account_name(portfolio, summaries)))
+        .collect();
+    write_tax_summaries(&viac_summaries)?;
+    let outgoing = outgoing_transfers(&viac_summaries);
     for (portfolio, mut summaries) in viac_summaries.into_iter() {
+        // e.g. tax documents without a portfolio, they are per contract
+        if !summaries
+            .iter()
+            .any(|s| s.is_account_transaction() || s.is_portfolio_transaction())
+        {
+            continue;
+        }
         summaries.sort_by_key(|s| s.valuta_date());
-        let mut file = std::fs::File::create(&format!("VIAC_{}_Account.csv", portfolio))?;
+        let account_name = &account_names[&portfolio];
+        let mut file = std::fs::File::create(format!("{account_name}_Account.csv"))?;
         let mut wtr = csv::Writer::from_writer(&mut file);
         wtr.write_record(header)?;
         summaries
             .iter()
+            .filter(|s| s.is_account_transaction())
             .filter(|s| {
-                !matches!(
-                    s.document_type,
-                    ViacDocument::Purchase(_) | ViacDocument::Sale(_)
-                )
+                // PP books both sides of a transfer, VIAC sends a PDF for each side
+                if booked_with_outgoing_side(s, &portfolio, &outgoing) {
+                    log::info!("{}: transfer booked with the outgoing side", s.comment);
+                    return false;
+                }
+                if matches!(s.document_type, ViacDocument::TransferIn(_))
+                    && matches!(s.counterparty(), Some(c) if account_names.contains_key(c))
+                {
+                    log::warn!(
+                        "{}: no outgoing side of this transfer found, booked from the incoming side",
+                        s.comment
+                    );
+                }
+                true
             })
             .for_each(|summary| {
                 let (valuta_price, valuta_currency) = summary.valuta_price();
@@ -137,10 +176,26 @@ pub fn write_summaries(
                     // no ISIN means there are fees or interest rates coming in
                     exchange_rate = summary.exchange_rate(Decimal::ONE);
                 }
+                // transfers between VIAC portfolios name both PP accounts so PP can link them,
+                // another foundation is no PP account, its transfers are deposits and removals
+                let no_accounts = ("".to_owned(), "".to_owned());
+                let (order_type, accounts) = match summary.counterparty() {
+                    Some(c) => match account_names.get(c) {
+                        Some(other) => (
+                            summary.order_type(),
+                            (account_name.to_owned(), other.to_owned()),
+                        ),
+                        None if matches!(summary.document_type, ViacDocument::TransferIn(_)) => {
+                            ("DEPOSIT".to_owned(), no_accounts)
+                        }
+                        None => ("REMOVAL".to_owned(), no_accounts),
+                    },
+                    None => (summary.order_type(), no_accounts),
+                };
 
                 wtr.write_record(&[
                     summary.valuta_date().to_string(), //"Datum",
-                    summary.order_type(),              //"Typ",
+                    order_type,                        //"Typ",
                     valuta_price,                      //"Wert",
                     valuta_currency,                   //"Buchungswährung",
                     total_price,                       //"Bruttobetrag",
@@ -151,20 +206,18 @@ pub fn write_summaries(
                     summary.shares(),                  //"Stück"
                     isin,                              //"ISIN"
                     summary.comment.to_owned(),
+                    accounts.0, //"Konto"
+                    accounts.1, //"Gegenkonto"
                 ])
                 .unwrap();
             });
-        let mut file = std::fs::File::create(&format!("VIAC_{}_Portfolio.csv", portfolio))?;
+        let mut file = std::fs::File::create(format!("{account_name}_Portfolio.csv"))?;
         let mut wtr = csv::Writer::from_writer(&mut file);
-        wtr.write_record(header)?;
+        // accounts only link transfers, which are account transactions
+        wtr.write_record(&header[..header.len() - 2])?;
         summaries
             .iter()
-            .filter(|s| {
-                matches!(
-                    s.document_type,
-                    ViacDocument::Purchase(_) | ViacDocument::Sale(_)
-                )
-            })
+            .filter(|s| s.is_portfolio_transaction())
             .for_each(|summary| {
                 let (valuta_price, valuta_currency) = summary.valuta_price();
                 let isin = summary.isin();
@@ -201,3 +254,361 @@ pub fn write_summaries(
     }
     Ok(())
 }
+
+/// Writes `<ISIN>_Prices.csv` per security in the format of PP's historical prices import,
+/// from the execution prices of the trades and the prices of the annual statements.
+/// isin -> (currency of the first trade, date -> prices)
+type DailyPrices = BTreeMap<String, (Money, BTreeMap<NaiveDate, Vec<Decimal>>)>;
+
+/// trade and statement prices per ISIN and day, those in another currency than the first trade left out
+fn daily_prices(viac_summaries: &HashMap<String, Vec<ViacSummary>>) -> DailyPrices {
+    let mut prices = DailyPrices::new();
+    let summaries = viac_summaries.values().flatten();
+    let mut trades: Vec<&ViacSummary> = summaries.clone().collect();
+    trades.sort_by_key(|s| s.valuta_date());
+    for s in trades {
+        if let ViacDocument::Purchase(t) | ViacDocument::Sale(t) = &s.document_type {
+            let (first, days) = prices
+                .entry(s.isin())
+                .or_insert_with(|| (t.share_price(), BTreeMap::new()));
+            if first.currency != t.share_price().currency {
+                log::warn!(
+                    "{}: trade price {} left out of the price history, first traded in {}",
+                    s.comment,
+                    t.share_price(),
+                    first
+                );
+                continue;
+            }
+            days.entry(s.valuta_date().date())
+                .or_default()
+                .push(t.share_price().amount);
+        }
+    }
+    for s in summaries {
+        if let ViacDocument::AnnualStatement(a) = &s.document_type {
+            for h in &a.holdings {
+                match prices.get_mut(&h.isin) {
+                    Some((trade, days)) if trade.currency == h.price.currency => days
+                        .entry(a.valuation_date.date())
+                        .or_default()
+                        .push(h.price.amount),
+                    _ => log::debug!("{}: statement price {} left out", h.isin, h.price),
+                }
+            }
+        }
+    }
+    prices
+}
+
+/// The trade PDFs state no trade date, their prices are dated to the valuta date, usually a few days later.
+/// Several prices of a day are averaged.
+fn write_price_history(
+    viac_summaries: &HashMap<String, Vec<ViacSummary>>,
+    isin_currency: &HashMap<String, [u8; 3]>,
+) -> std::io::Result<()> {
+    for (isin, (_, days)) in daily_prices(viac_summaries) {
+        let conversion_rate = match isin_currency.get(&isin) {
+            Some([b'G', b'B', b'X']) => Decimal::new(100, 0),
+            _ => Decimal::ONE,
+        };
+        let mut file = std::fs::File::create(format!("{isin}_Prices.csv"))?;
+        let mut wtr = csv::Writer::from_writer(&mut file);
+        wtr.write_record(["Datum", "Kurs"])?;
+        for (day, day_prices) in days {
+            let average = day_prices.iter().sum::<Decimal>() / Decimal::from(day_prices.len());
+            wtr.write_record(&[
+                day.format("%Y-%m-%d").to_string(),
+                (average * conversion_rate).round_dp(4).to_string(),
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+/// outgoing transfers as (portfolio, counterparty, valuta date, amount)
+type OutgoingTransfer = (String, String, NaiveDateTime, Decimal);
+
+fn outgoing_transfers(
+    viac_summaries: &HashMap<String, Vec<ViacSummary>>,
+) -> HashSet<OutgoingTransfer> {
+    viac_summaries
+        .iter()
+        .flat_map(|(portfolio, summaries)| {
+            summaries
+                .iter()
+                .filter_map(move |s| match &s.document_type {
+                    ViacDocument::TransferOut(_) => Some((
+                        portfolio.to_owned(),
+                        s.counterparty()?.to_owned(),
+                        s.valuta_date(),
+                        -s.cash_flow()?.amount,
+                    )),
+                    _ => None,
+                })
+        })
+        .collect()
+}
+
+/// an incoming transfer whose outgoing side, same portfolios, day and amount, is exported already
+fn booked_with_outgoing_side(
+    s: &ViacSummary,
+    portfolio: &str,
+    outgoing: &HashSet<OutgoingTransfer>,
+) -> bool {
+    if !matches!(s.document_type, ViacDocument::TransferIn(_)) {
+        return false;
+    }
+    match (s.counterparty(), s.cash_flow()) {
+        (Some(counterparty), Some(amount)) => outgoing.contains(&(
+            counterparty.to_owned(),
+            portfolio.to_owned(),
+            s.valuta_date(),
+            amount.amount,
+        )),
+        _ => false,
+    }
+}
+
+/// not every document names the foundation, the first one that does decides
+pub(crate) fn account_name<'a>(
+    portfolio: &str,
+    summaries: impl IntoIterator<Item = &'a ViacSummary>,
+) -> String {
+    summaries
+        .into_iter()
+        .map(|s| s.product_type)
+        .find(|p| *p != ProductType::Unknown)
+        .unwrap_or(ProductType::Unknown)
+        .account_name(portfolio)
+}
+
+#[derive(Default)]
+struct TaxYear {
+    deposits: Decimal,
+    contributions: Option<Decimal>,
+    withholding_tax: Option<Decimal>,
+    foreign_withholding_tax: Option<Decimal>,
+}
+
+/// Per contract the yearly deposits into all its portfolios next to the figures of its tax documents,
+/// VIAC issues those per contract
+fn write_tax_summaries(viac_summaries: &HashMap<String, Vec<ViacSummary>>) -> std::io::Result<()> {
+    let mut contracts: BTreeMap<&str, Vec<&ViacSummary>> = BTreeMap::new();
+    for s in viac_summaries.values().flatten() {
+        contracts.entry(&s.account_number).or_default().push(s);
+    }
+    for (contract, summaries) in contracts {
+        let is_tax_document = |s: &ViacSummary| {
+            matches!(
+                s.document_type,
+                ViacDocument::ContributionCertificate(_) | ViacDocument::TaxStatement(_)
+            )
+        };
+        if !summaries.iter().any(|s| is_tax_document(s)) {
+            continue;
+        }
+        if contract.is_empty() {
+            for s in summaries.iter().filter(|s| is_tax_document(s)) {
+                log::warn!(
+                    "{}: tax document without contract number left out",
+                    s.comment
+                );
+            }
+            continue;
+        }
+        write_tax_summary(
+            &account_name(contract, summaries.iter().copied()),
+            &summaries,
+        )?;
+    }
+    Ok(())
+}
+
+/// deposits and tax document figures per year
+fn tax_years(summaries: &[&ViacSummary]) -> BTreeMap<i32, TaxYear> {
+    let mut years: BTreeMap<i32, TaxYear> = BTreeMap::new();
+    for s in summaries {
+        match &s.document_type {
+            ViacDocument::Incoming(_) => {
+                if let Some(deposit) = s.cash_flow() {
+                    years.entry(s.valuta_date().year()).or_default().deposits += deposit.amount;
+                }
+            }
+            ViacDocument::ContributionCertificate(t) => {
+                let y = years.entry(t.year).or_default();
+                y.contributions = t.contributions.map(|m| m.amount).or(y.contributions);
+            }
+            ViacDocument::TaxStatement(t) => {
+                let y = years.entry(t.year).or_default();
+                y.withholding_tax = t.withholding_tax.map(|m| m.amount).or(y.withholding_tax);
+                y.foreign_withholding_tax = t
+                    .foreign_withholding_tax
+                    .map(|m| m.amount)
+                    .or(y.foreign_withholding_tax);
+            }
+            _ => {}
+        }
+    }
+    years
+}
+
+fn write_tax_summary(account_name: &str, summaries: &[&ViacSummary]) -> std::io::Result<()> {
+    let opt = |d: Option<Decimal>| d.map_or("".to_string(), |d| d.to_string());
+    let mut file = std::fs::File::create(format!("{account_name}_Tax_Summary.csv"))?;
+    let mut wtr = csv::Writer::from_writer(&mut file);
+    wtr.write_record([
+        "Jahr",
+        "Einzahlungen",
+        "Bescheinigte Beiträge",
+        "Differenz",
+        "Verrechnungssteuer",
+        "Quellensteuer",
+    ])?;
+    for (year, y) in tax_years(summaries) {
+        wtr.write_record(&[
+            year.to_string(),
+            y.deposits.to_string(),
+            opt(y.contributions),
+            opt(y.contributions.map(|c| y.deposits - c)),
+            opt(y.withholding_tax),
+            opt(y.foreign_withholding_tax),
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viac_pdf::fixtures::{cash, money, statement, summary, trade, transfer};
+    use crate::viac_pdf::{midnight, ViacTaxStatement};
+
+    #[test]
+    fn tax_years_put_deposits_next_to_certified_figures() {
+        let docs = [
+            summary(ViacDocument::Incoming(cash(
+                midnight(2023, 1, 10),
+                "CHF 3'000.00",
+            ))),
+            summary(ViacDocument::Incoming(cash(
+                midnight(2023, 6, 10),
+                "CHF 4'056.00",
+            ))),
+            summary(ViacDocument::Incoming(cash(
+                midnight(2024, 1, 10),
+                "CHF 500.00",
+            ))),
+            summary(ViacDocument::ContributionCertificate(ViacTaxStatement {
+                year: 2023,
+                contributions: Some(money("CHF 7'056.00")),
+                withholding_tax: None,
+                foreign_withholding_tax: None,
+            })),
+            summary(ViacDocument::TaxStatement(ViacTaxStatement {
+                year: 2023,
+                contributions: None,
+                withholding_tax: Some(money("CHF 12.50")),
+                foreign_withholding_tax: Some(money("CHF 8.20")),
+            })),
+        ];
+        let docs: Vec<&ViacSummary> = docs.iter().collect();
+        let years = tax_years(&docs);
+        let y = &years[&2023];
+        assert_eq!(y.deposits.to_string(), "7056.00");
+        assert_eq!(y.contributions.unwrap().to_string(), "7056.00");
+        assert_eq!(y.withholding_tax.unwrap().to_string(), "12.50");
+        assert_eq!(y.foreign_withholding_tax.unwrap().to_string(), "8.20");
+        let y = &years[&2024];
+        assert_eq!(y.deposits.to_string(), "500.00");
+        assert!(y.contributions.is_none());
+    }
+
+    #[test]
+    fn incoming_transfer_is_dropped_only_next_to_its_outgoing_side() {
+        let in_portfolio = |portfolio: &str, document_type| {
+            let mut s = summary(document_type);
+            s.portfolio_number = portfolio.to_string();
+            s
+        };
+        let day = midnight(2023, 5, 2);
+        let mut all_docs = HashMap::new();
+        all_docs.insert(
+            "1".to_string(),
+            vec![in_portfolio(
+                "1",
+                ViacDocument::TransferOut(transfer(day, "CHF 1'000.00", "2")),
+            )],
+        );
+        let outgoing = outgoing_transfers(&all_docs);
+        let incoming = |amount, counterparty| {
+            in_portfolio(
+                "2",
+                ViacDocument::TransferIn(transfer(day, amount, counterparty)),
+            )
+        };
+        assert!(booked_with_outgoing_side(
+            &incoming("CHF 1'000.00", "1"),
+            "2",
+            &outgoing
+        ));
+        assert!(!booked_with_outgoing_side(
+            &incoming("CHF 999.00", "1"),
+            "2",
+            &outgoing
+        ));
+        assert!(!booked_with_outgoing_side(
+            &incoming("CHF 1'000.00", "3"),
+            "2",
+            &outgoing
+        ));
+        assert!(!booked_with_outgoing_side(
+            &incoming("CHF 1'000.00", "1"),
+            "3",
+            &outgoing
+        ));
+    }
+
+    #[test]
+    fn price_history_keeps_the_currency_of_the_first_trade() {
+        let isin = "IE00B5BMR087";
+        let mut all_docs = HashMap::new();
+        all_docs.insert(
+            "1".to_string(),
+            vec![
+                summary(ViacDocument::Purchase(trade(
+                    midnight(2023, 3, 1),
+                    isin,
+                    "1",
+                    "USD 400.00",
+                ))),
+                summary(ViacDocument::Purchase(trade(
+                    midnight(2023, 3, 1),
+                    isin,
+                    "1",
+                    "USD 402.00",
+                ))),
+                summary(ViacDocument::Sale(trade(
+                    midnight(2023, 4, 1),
+                    isin,
+                    "1",
+                    "CHF 370.00",
+                ))),
+                summary(ViacDocument::AnnualStatement(statement(
+                    midnight(2023, 12, 31),
+                    &[(isin, "1", "USD 450.00")],
+                    "CHF 0.00",
+                ))),
+            ],
+        );
+        let prices = daily_prices(&all_docs);
+        let (first, days) = &prices[isin];
+        assert_eq!(first.to_string(), "USD 400.00");
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[&NaiveDate::from_ymd_opt(2023, 3, 1).unwrap()].len(), 2);
+        assert_eq!(
+            days[&NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()],
+            vec![Decimal::from(450)]
+        );
+    }
+}
@@ -1,26 +1,65 @@
 use std::convert::{AsRef, From};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::str::FromStr;
 
 use chrono::{NaiveDate, NaiveDateTime};
-use log::debug;
+use log::{debug, warn};
 use pdf::error::PdfError;
 use pdf::file::FileOptions;
 use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
 
-use crate::money::Money;
+use crate::classify::{classify, ClassificationRule, DocumentKind, Rule};
+use crate::language::{self, Language};
+use crate::money::{Money, CHF};
 use crate::pdf_text;
+use crate::template::{ExtractionTemplate, TemplateError};
 
 pub enum ViacPdf {
     French(ViacPdfFrench),
     German(ViacPdfGerman),
+    Template(ViacPdfTemplate),
+}
+
+#[derive(Default)]
+pub struct OpenOptions<'a> {
+    pub password: Option<&'a str>,
+    /// user templates, checked before the builtin languages
+    pub templates: &'a [Rc<ExtractionTemplate>],
+    /// skips language detection
+    pub language: Option<Language>,
+}
+
+#[derive(Debug, Error)]
+pub enum ViacPdfError {
+    #[error("encrypted, password required")]
+    PasswordRequired,
+    #[error("encrypted, wrong password")]
+    WrongPassword,
+    #[error("pdf backend failed: {0}")]
+    Pdf(#[from] PdfError),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error("{0}: {1:?} without {2}, unknown layout")]
+    MissingField(String, DocumentKind, &'static str),
 }
 
 impl ViacPdf {
     pub fn from_path(
         path: impl Into<PathBuf> + AsRef<Path> + AsRef<std::ffi::OsStr>,
-    ) -> Result<Self, PdfError> {
-        let file = FileOptions::cached().open(&path).unwrap();
+        options: &OpenOptions,
+    ) -> Result<Self, ViacPdfError> {
+        let password = options.password;
+        let file = FileOptions::cached()
+            .password(password.unwrap_or_default().as_bytes())
+            .open(&path)
+            .map_err(|e| match (e, password) {
+                (PdfError::InvalidPassword, None) => ViacPdfError::PasswordRequired,
+                (PdfError::InvalidPassword, Some(_)) => ViacPdfError::WrongPassword,
+                (e, _) => ViacPdfError::Pdf(e),
+            })?;
         let mut title = None;
         let mut author = None;
         if let Some(ref info) = file.trailer.info_dict {
@@ -28,20 +67,39 @@ impl ViacPdf {
             author = info.get("Author").map(|p| p.to_string_lossy().unwrap());
         }
         let pages = pdf_text::pdf2strings(file)?;
-        if pages[0].contains("de la Banque WIR") {
-            Ok(ViacPdf::French(ViacPdfFrench(ViacPdfData {
-                path: PathBuf::from(&path),
-                title,
-                author,
-                pages,
-            })))
+        if let Some(template) = options.templates.iter().find(|t| t.detects(&pages[0])) {
+            Ok(ViacPdf::Template(ViacPdfTemplate {
+                data: ViacPdfData {
+                    path: PathBuf::from(&path),
+                    title,
+                    author,
+                    pages,
+                },
+                template: template.clone(),
+            }))
         } else {
-            Ok(ViacPdf::German(ViacPdfGerman(ViacPdfData {
-                path: PathBuf::from(&path),
+            let path = PathBuf::from(&path);
+            let language = options.language.unwrap_or_else(|| {
+                language::detect_or_default(&path.display().to_string(), &pages[0])
+            });
+            let data = ViacPdfData {
+                path,
                 title,
                 author,
                 pages,
-            })))
+            };
+            match language {
+                Language::French => Ok(ViacPdf::French(ViacPdfFrench(data))),
+                Language::German => Ok(ViacPdf::German(ViacPdfGerman(data))),
+            }
+        }
+    }
+
+    pub fn extractor(&self) -> &dyn ViacPdfExtractor {
+        match self {
+            ViacPdf::French(p) => p,
+            ViacPdf::German(p) => p,
+            ViacPdf::Template(p) => p,
         }
     }
 }
@@ -53,8 +111,281 @@ struct ViacPdfData {
     pages: Vec<String>,
 }
 
+/// generic words like fees or interest also show up on trade and dividend documents
+const GERMAN_TRADES: &[&str] = &["Börsenabrechnung", "Dividendenausschüttung"];
+
+/// and on statements, which list the bookings of a whole year,
+/// these are recognized by their title line as other documents refer to them in passing
+const GERMAN_TRADES_AND_STATEMENTS: &[&str] = &[
+    "Börsenabrechnung",
+    "Dividendenausschüttung",
+    "^Vermögensausweis",
+    "^Jahresauszug",
+    "^Steuerbescheinigung",
+    "^Steuerauszug",
+];
+
+/// the first matching rule wins, statements come first as they list every kind of booking
+const GERMAN_RULES: &[ClassificationRule] = &[
+    ClassificationRule {
+        name: "Vermögensausweis",
+        markers: &["^Vermögensausweis"],
+        exclusions: GERMAN_TRADES,
+        kind: DocumentKind::AnnualStatement,
+    },
+    ClassificationRule {
+        name: "Jahresauszug",
+        markers: &["^Jahresauszug"],
+        exclusions: GERMAN_TRADES,
+        kind: DocumentKind::AnnualStatement,
+    },
+    ClassificationRule {
+        name: "Steuerbescheinigung",
+        markers: &["^Steuerbescheinigung"],
+        exclusions: GERMAN_TRADES,
+        kind: DocumentKind::ContributionCertificate,
+    },
+    ClassificationRule {
+        name: "Steuerauszug",
+        markers: &["^Steuerauszug"],
+        exclusions: GERMAN_TRADES,
+        kind: DocumentKind::TaxStatement,
+    },
+    ClassificationRule {
+        name: "Börsenabrechnung - Kauf",
+        markers: &["Börsenabrechnung - Kauf"],
+        exclusions: &[],
+        kind: DocumentKind::Purchase,
+    },
+    ClassificationRule {
+        name: "Börsenabrechnung - Verkauf",
+        markers: &["Börsenabrechnung - Verkauf"],
+        exclusions: &[],
+        kind: DocumentKind::Sale,
+    },
+    ClassificationRule {
+        name: "Rückerstattung Quellensteuer",
+        markers: &["Dividendenausschüttung", "Rückerstattung Quellensteuer"],
+        exclusions: &[],
+        kind: DocumentKind::TaxReturn,
+    },
+    ClassificationRule {
+        name: "Korrektur Dividendenausschüttung",
+        markers: &["Korrektur Dividendenausschüttung"],
+        exclusions: &["Rückerstattung Quellensteuer"],
+        kind: DocumentKind::DividendCorrection,
+    },
+    ClassificationRule {
+        name: "Dividendenausschüttung",
+        markers: &["Dividendenausschüttung"],
+        exclusions: &[
+            "Rückerstattung Quellensteuer",
+            "Korrektur Dividendenausschüttung",
+        ],
+        kind: DocumentKind::Dividend,
+    },
+    ClassificationRule {
+        name: "Rückerstattung Verwaltungsgebühr",
+        markers: &["Rückerstattung Verwaltungsgebühr"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::FeesRefund,
+    },
+    ClassificationRule {
+        name: "Verwaltungsgebühr",
+        markers: &["Verwaltungsgebühr"],
+        exclusions: &[
+            "Börsenabrechnung",
+            "Dividendenausschüttung",
+            "Rückerstattung Verwaltungsgebühr",
+            "^Vermögensausweis",
+            "^Jahresauszug",
+            "^Steuerbescheinigung",
+            "^Steuerauszug",
+        ],
+        kind: DocumentKind::Fees,
+    },
+    ClassificationRule {
+        name: "Zinsgutschrift",
+        markers: &["Zinsgutschrift"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::Interest,
+    },
+    ClassificationRule {
+        name: "Zinsbelastung",
+        markers: &["Zinsbelastung"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::InterestCharge,
+    },
+    ClassificationRule {
+        name: "Zahlungseingang",
+        markers: &["Zahlungseingang"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::Incoming,
+    },
+    ClassificationRule {
+        name: "Eingang Übertrag",
+        markers: &["Eingang Übertrag"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::TransferIn,
+    },
+    ClassificationRule {
+        name: "Ausgang Übertrag",
+        markers: &["Ausgang Übertrag"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::TransferOut,
+    },
+    ClassificationRule {
+        name: "Einlieferung",
+        markers: &["Einlieferung"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::DeliveryIn,
+    },
+    ClassificationRule {
+        name: "Auslieferung",
+        markers: &["Auslieferung"],
+        exclusions: GERMAN_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::DeliveryOut,
+    },
+];
+
+const FRENCH_TRADES: &[&str] = &["Opération de bourse", "Avis de dividende"];
+
+const FRENCH_TRADES_AND_STATEMENTS: &[&str] = &[
+    "Opération de bourse",
+    "Avis de dividende",
+    "^Relevé de fortune",
+    "^Relevé annuel",
+    "^Attestation fiscale",
+    "^Relevé fiscal",
+];
+
+const FRENCH_RULES: &[ClassificationRule] = &[
+    ClassificationRule {
+        name: "Relevé de fortune",
+        markers: &["^Relevé de fortune"],
+        exclusions: FRENCH_TRADES,
+        kind: DocumentKind::AnnualStatement,
+    },
+    ClassificationRule {
+        name: "Relevé annuel",
+        markers: &["^Relevé annuel"],
+        exclusions: FRENCH_TRADES,
+        kind: DocumentKind::AnnualStatement,
+    },
+    ClassificationRule {
+        name: "Attestation fiscale",
+        markers: &["^Attestation fiscale"],
+        exclusions: FRENCH_TRADES,
+        kind: DocumentKind::ContributionCertificate,
+    },
+    ClassificationRule {
+        name: "Relevé fiscal",
+        markers: &["^Relevé fiscal"],
+        exclusions: FRENCH_TRADES,
+        kind: DocumentKind::TaxStatement,
+    },
+    ClassificationRule {
+        name: "Opération de bourse - Achat",
+        markers: &["Opération de bourse - Achat"],
+        exclusions: &[],
+        kind: DocumentKind::Purchase,
+    },
+    ClassificationRule {
+        name: "Opération de bourse - Vente",
+        markers: &["Opération de bourse - Vente"],
+        exclusions: &[],
+        kind: DocumentKind::Sale,
+    },
+    ClassificationRule {
+        name: "Remboursement d'impôt à la source",
+        markers: &["Avis de dividende", "Remboursement d'impôt à la source"],
+        exclusions: &[],
+        kind: DocumentKind::TaxReturn,
+    },
+    ClassificationRule {
+        name: "Avis de dividende",
+        markers: &["Avis de dividende"],
+        exclusions: &["Remboursement d'impôt à la source"],
+        kind: DocumentKind::Dividend,
+    },
+    ClassificationRule {
+        name: "Remboursement de commission",
+        markers: &["Remboursement de commission"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::FeesRefund,
+    },
+    ClassificationRule {
+        name: "Commission",
+        markers: &["Commission"],
+        exclusions: &[
+            "Opération de bourse",
+            "Avis de dividende",
+            "Remboursement de commission",
+            "^Relevé de fortune",
+            "^Relevé annuel",
+            "^Attestation fiscale",
+            "^Relevé fiscal",
+        ],
+        kind: DocumentKind::Fees,
+    },
+    ClassificationRule {
+        name: "Intérêts négatifs",
+        markers: &["Intérêts négatifs"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::InterestCharge,
+    },
+    ClassificationRule {
+        name: "Intérêts",
+        markers: &["Intérêts"],
+        exclusions: &[
+            "Opération de bourse",
+            "Avis de dividende",
+            "Intérêts négatifs",
+            "^Relevé de fortune",
+            "^Relevé annuel",
+            "^Attestation fiscale",
+            "^Relevé fiscal",
+        ],
+        kind: DocumentKind::Interest,
+    },
+    ClassificationRule {
+        name: "Avis de versement",
+        markers: &["Avis de versement"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::Incoming,
+    },
+    ClassificationRule {
+        name: "Transfert entrant",
+        markers: &["Transfert entrant"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::TransferIn,
+    },
+    ClassificationRule {
+        name: "Transfert sortant",
+        markers: &["Transfert sortant"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::TransferOut,
+    },
+    ClassificationRule {
+        name: "Entrée de titres",
+        markers: &["Entrée de titres"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::DeliveryIn,
+    },
+    ClassificationRule {
+        name: "Sortie de titres",
+        markers: &["Sortie de titres"],
+        exclusions: FRENCH_TRADES_AND_STATEMENTS,
+        kind: DocumentKind::DeliveryOut,
+    },
+];
+
 pub struct ViacPdfGerman(ViacPdfData);
 pub struct ViacPdfFrench(ViacPdfData);
+pub struct ViacPdfTemplate {
+    data: ViacPdfData,
+    template: Rc<ExtractionTemplate>,
+}
 
 pub trait ViacPdfExtractor {
     fn transaction(&self) -> ViacTransaction {
@@ -63,7 +394,7 @@ pub trait ViacPdfExtractor {
             shares: self.shares(),
             share_price: self.share_price(),
             total_price: self.total_price(),
-            taxes: self.taxes(),
+            costs: self.costs(),
             valuta_price: self.valuta_price(),
             isin: self.isin(),
             share_title: self.share_title(),
@@ -81,16 +412,97 @@ pub trait ViacPdfExtractor {
             dividend_price: self.dividend_price(),
             total_price: self.total_price(),
             exchange_rate: self.exchange_rate(),
+            withholding_tax: self.withholding_tax(),
+            ex_date: self.ex_date(),
+        }
+    }
+
+    fn transfer(&self) -> ViacTransfer {
+        ViacTransfer {
+            valuta_date: self.valuta_date(),
+            valuta_price: self.valuta_price(),
+            counterparty: self.counterparty(),
         }
     }
 
-    fn summary(&self, deduce: bool) -> Result<ViacSummary, PdfError> {
+    fn delivery(&self) -> ViacDelivery {
+        let share_price = self.share_price();
+        ViacDelivery {
+            valuta_date: self.valuta_date(),
+            isin: self.isin(),
+            share_title: self.share_title(),
+            shares: self.shares(),
+            chf_rate: (share_price.currency == CHF).then_some(Decimal::ONE),
+            share_price,
+        }
+    }
+
+    fn document_type(&self) -> Result<ViacDocument, ViacPdfError> {
+        if self.author() != Some("VIAC") {
+            return Ok(ViacDocument::NotViac);
+        }
+        let Some(kind) = self.document_kind() else {
+            return Ok(ViacDocument::Unknown);
+        };
+        self.check(kind)?;
+        Ok(match kind {
+            DocumentKind::Purchase => ViacDocument::Purchase(self.transaction()),
+            DocumentKind::Sale => ViacDocument::Sale(self.transaction()),
+            DocumentKind::Dividend => ViacDocument::Dividend(self.dividend()),
+            DocumentKind::DividendCorrection => ViacDocument::Unknown, // TODO: treat storno of dividends
+            DocumentKind::TaxReturn => ViacDocument::TaxReturn(self.dividend()),
+            DocumentKind::Fees => ViacDocument::Fees(ViacValuta {
+                valuta_price: self.valuta_price(),
+                valuta_date: self.valuta_date(),
+            }),
+            DocumentKind::FeesRefund => ViacDocument::FeesRefund(ViacValuta {
+                valuta_price: self.valuta_price(),
+                valuta_date: self.valuta_date(),
+            }),
+            DocumentKind::Interest => ViacDocument::Interest(ViacValuta {
+                valuta_price: self.interest_price(),
+                valuta_date: self.interest_date(),
+            }),
+            DocumentKind::InterestCharge => ViacDocument::InterestCharge(ViacValuta {
+                valuta_price: self.valuta_price(),
+                valuta_date: self.valuta_date(),
+            }),
+            DocumentKind::Incoming => ViacDocument::Incoming(ViacValuta {
+                valuta_price: self.valuta_price(),
+                valuta_date: self.valuta_date(),
+            }),
+            DocumentKind::TransferIn => ViacDocument::TransferIn(self.transfer()),
+            DocumentKind::TransferOut => ViacDocument::TransferOut(self.transfer()),
+            DocumentKind::DeliveryIn => ViacDocument::DeliveryIn(self.delivery()),
+            DocumentKind::DeliveryOut => ViacDocument::DeliveryOut(self.delivery()),
+            DocumentKind::AnnualStatement => match self.annual_statement() {
+                Some(a) => ViacDocument::AnnualStatement(a),
+                None => {
+                    warn!("{}: statement has an unknown layout", self.filename());
+                    ViacDocument::Unknown
+                }
+            },
+            DocumentKind::ContributionCertificate | DocumentKind::TaxStatement => {
+                match (kind, self.tax_statement()) {
+                    (DocumentKind::TaxStatement, Some(t)) => ViacDocument::TaxStatement(t),
+                    (_, Some(t)) => ViacDocument::ContributionCertificate(t),
+                    (_, None) => {
+                        warn!("{}: tax document states no tax year", self.filename());
+                        ViacDocument::Unknown
+                    }
+                }
+            }
+        })
+    }
+
+    fn summary(&self, deduce: bool) -> Result<ViacSummary, ViacPdfError> {
         let document_type = self.document_type()?;
         let (account_number, portfolio_number) = self.account_numbers();
         Ok(ViacSummary {
             deduce,
             account_number,
             portfolio_number,
+            product_type: self.product_type(),
             comment: format!("viac_pdf_import {}", self.filename()),
             document_type,
         })
@@ -98,20 +510,32 @@ pub trait ViacPdfExtractor {
 
     fn valuta_date(&self) -> NaiveDateTime;
     fn interest_date(&self) -> NaiveDateTime;
+    fn ex_date(&self) -> Option<NaiveDateTime>;
     fn shares(&self) -> Decimal;
     fn share_price(&self) -> Money;
     fn total_price(&self) -> Money;
-    fn taxes(&self) -> Option<Money>;
+    fn costs(&self) -> Vec<TradeCost>;
+    fn withholding_tax(&self) -> Option<Money>;
     fn valuta_price(&self) -> Money;
     fn isin(&self) -> String;
     fn share_title(&self) -> String;
     fn exchange_rate(&self) -> Option<ExchangeRate>;
-    fn document_type(&self) -> Result<ViacDocument, PdfError>;
+    fn author(&self) -> Option<&str>;
+    fn first_page(&self) -> &str;
+    fn document_kind(&self) -> Option<DocumentKind>;
+    /// whether the document has what extracting a `kind` needs, the builtin extractors trust the layout
+    fn check(&self, _kind: DocumentKind) -> Result<(), ViacPdfError> {
+        Ok(())
+    }
     fn filename(&self) -> String;
     fn account_numbers(&self) -> (String, String);
+    fn product_type(&self) -> ProductType;
     fn exchange_rate_value(&self) -> Decimal;
     fn dividend_price(&self) -> Money;
     fn interest_price(&self) -> Money;
+    fn annual_statement(&self) -> Option<ViacAnnualStatement>;
+    fn counterparty(&self) -> String;
+    fn tax_statement(&self) -> Option<ViacTaxStatement>;
     fn print_summary(&self);
 }
 
@@ -136,55 +560,156 @@ impl ViacPdfData {
             .to_string()
     }
 
-    fn isin(&self) -> String {
-        let mut last_line = "";
-        for line in self.pages[0].lines() {
-            if last_line.starts_with("ISIN:") {
-                assert!(!line.is_empty());
-                return line.to_string();
-            }
-            last_line = line;
+    /// the line `offset` lines away from the first line `marker` matches
+    fn line_near(&self, marker: impl Fn(&str) -> bool, offset: isize) -> Option<&str> {
+        let lines: Vec<&str> = self.pages[0].lines().collect();
+        let i = lines.iter().position(|line| marker(line))?;
+        lines.get(i.checked_add_signed(offset)?).copied()
+    }
+
+    fn isin(&self) -> Option<&str> {
+        self.line_near(|line| line.starts_with("ISIN:"), 1)
+            .filter(|isin| !isin.is_empty())
+    }
+
+    /// date of the first line reading like `format`, e.g. `Valuta %d.%m.%Y`
+    fn date_line(&self, format: &str) -> Option<NaiveDateTime> {
+        self.pages[0]
+            .lines()
+            .find_map(|line| NaiveDate::parse_from_str(line, format).ok())?
+            .and_hms_opt(0, 0, 0)
+    }
+
+    /// Transfers, deliveries, fee refunds and interest charges follow layouts guessed from the other documents,
+    /// a document missing a field they need is an error rather than a panic
+    fn check_guessed(&self, kind: DocumentKind, found: GuessedFields) -> Result<(), ViacPdfError> {
+        let required = match kind {
+            DocumentKind::TransferIn
+            | DocumentKind::TransferOut
+            | DocumentKind::FeesRefund
+            | DocumentKind::InterestCharge => vec![
+                ("valuta date", found.valuta_date),
+                ("valuta amount", found.valuta_price),
+            ],
+            DocumentKind::DeliveryIn | DocumentKind::DeliveryOut => vec![
+                ("valuta date", found.valuta_date),
+                ("ISIN", found.isin),
+                ("share title", found.share_title),
+                ("shares", found.shares),
+                ("share price", found.share_price),
+            ],
+            _ => vec![],
+        };
+        match required.into_iter().find(|(_, found)| !found) {
+            Some((field, _)) => Err(ViacPdfError::MissingField(self.filename(), kind, field)),
+            None => Ok(()),
         }
-        unreachable!();
     }
     /// Returns a Money struct from 3line text pattern:
     /// Title
     /// currency
     /// amount
+    /// None when no line starting with the title is followed by currency and amount
     fn title_currency_amount(&self, title: &str) -> Option<Money> {
-        let mut currency = "";
+        let lines: Vec<&str> = self.pages[0].lines().collect();
+        currency_amount_after(&lines, title)
+    }
+
+    /// all cost lines of a trade confirmation found by their titles
+    fn costs(&self, titles: &[(&str, CostKind)]) -> Vec<TradeCost> {
+        titles
+            .iter()
+            .filter_map(|(title, kind)| {
+                self.title_currency_amount(title).map(|amount| TradeCost {
+                    kind: *kind,
+                    amount,
+                })
+            })
+            .collect()
+    }
+
+    /// `currency amount` on the line after `content`
+    fn money_after_line(&self, content: &str) -> Option<Money> {
+        let line = self.line_near(|line| line == content, 1)?;
+        Some(Money::new(line.get(..3)?, amount_in_line(line.get(4..)?)?))
+    }
+
+    /// the line following the first of the titles found
+    fn line_after(&self, titles: &[&str]) -> Option<String> {
         let mut last_line = "";
         for line in self.pages[0].lines() {
-            if last_line.starts_with(title) {
-                currency = &line[..3];
-                last_line = line;
-                continue;
+            if titles.contains(&last_line) {
+                return Some(line.to_string());
             }
-            if !currency.is_empty() {
-                // sometimes conversion rate is on an extra line, skip it
-                if currency.contains('.') {
-                    currency = &line[..3];
-                    last_line = line;
-                    continue;
+            last_line = line;
+        }
+        None
+    }
+
+    /// year at the end of the first line starting with title, e.g. `Steuerjahr 2023`
+    fn year_after(&self, title: &str) -> Option<i32> {
+        self.pages[0]
+            .lines()
+            .filter(|line| line.starts_with(title))
+            .find_map(|line| line.rsplit(' ').next().and_then(|y| i32::from_str(y).ok()))
+    }
+
+    /// statements run over several pages, returns the lines of all pages
+    fn all_lines(&self) -> Vec<&str> {
+        self.pages.iter().flat_map(|p| p.lines()).collect()
+    }
+
+    /// date at the end of the first line starting with one of the titles, e.g. `Vermögensausweis per 31.12.2023`
+    fn date_in_line(&self, titles: &[&str]) -> Option<NaiveDateTime> {
+        for line in self.all_lines() {
+            if titles.iter().any(|t| line.starts_with(t)) {
+                if let Some(Ok(date)) = line
+                    .rsplit(' ')
+                    .next()
+                    .map(|d| NaiveDate::parse_from_str(d, "%d.%m.%Y"))
+                {
+                    return Some(date.and_hms_opt(0, 0, 0).unwrap());
                 }
-                let price = line.replace('\'', "");
-                return Some(Money::new(currency, Decimal::from_str(&price).unwrap()));
             }
-            last_line = line;
         }
         None
     }
 
-    fn money_after_line(&self, content: &str) -> Money {
-        let mut last_line = "";
-        for line in self.pages[0].lines() {
-            if last_line == content {
-                let price = line[4..].replace('\'', "");
-                return Money::new(&line[..3], Decimal::from_str(&price).unwrap());
+    /// Returns the positions of a statement, each one is a block of lines around `ISIN:`
+    /// shares `shares_offset` lines above, title right above,
+    /// below the price line followed by `currency amount`
+    /// and the value title followed by currency and amount lines.
+    /// None when a position has another layout
+    fn holdings(
+        &self,
+        shares_offset: usize,
+        price_line: &str,
+        value_title: &str,
+    ) -> Option<Vec<ViacHolding>> {
+        let lines = self.all_lines();
+        let mut holdings = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            if !line.starts_with("ISIN:") {
+                continue;
+            }
+            match holding(&lines, i, shares_offset, price_line, value_title) {
+                Some(h) => holdings.push(h),
+                None => {
+                    warn!(
+                        "{}: position {:?} not readable",
+                        self.filename(),
+                        lines.get(i + 1).unwrap_or(line)
+                    );
+                    return None;
+                }
             }
-            last_line = line;
         }
-        unreachable!();
+        Some(holdings)
+    }
+
+    /// like title_currency_amount but searching all pages
+    fn statement_currency_amount(&self, title: &str) -> Option<Money> {
+        currency_amount_after(&self.all_lines(), title)
     }
 
     fn account_numbers(&self, account_line: &str, portfolio_line: &str) -> (String, String) {
@@ -207,6 +732,72 @@ impl ViacPdfData {
     }
 }
 
+/// the fields of the guessed layouts a builtin extractor finds on a document
+struct GuessedFields {
+    valuta_date: bool,
+    valuta_price: bool,
+    isin: bool,
+    share_title: bool,
+    shares: bool,
+    share_price: bool,
+}
+
+/// amount line of a statement, e.g. `1'234.50`
+fn amount_in_line(line: &str) -> Option<Decimal> {
+    Decimal::from_str(&line.replace('\'', "")).ok()
+}
+
+/// The amount of the first line starting with `title` that is followed by the lines
+/// currency and amount, the same title also starts lines of running text
+fn currency_amount_after(lines: &[&str], title: &str) -> Option<Money> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.starts_with(title))
+        .find_map(|(i, _)| {
+            let mut next = lines[i + 1..].iter();
+            let mut currency = next.next()?.get(..3)?;
+            // sometimes conversion rate is on an extra line, skip it
+            if currency.contains('.') {
+                currency = next.next()?.get(..3)?;
+            }
+            if !currency.bytes().all(|b| b.is_ascii_uppercase()) {
+                return None;
+            }
+            Some(Money::new(currency, amount_in_line(next.next()?)?))
+        })
+}
+
+/// the position of a statement at its `ISIN:` line `i`, searched up to the next position
+fn holding(
+    lines: &[&str],
+    i: usize,
+    shares_offset: usize,
+    price_line: &str,
+    value_title: &str,
+) -> Option<ViacHolding> {
+    let end = lines[i + 1..]
+        .iter()
+        .position(|l| l.starts_with("ISIN:"))
+        .map_or(lines.len(), |p| i + 1 + p);
+    let position = &lines[i..end];
+    let p = position.iter().position(|l| *l == price_line)?;
+    let price_line = position.get(p + 1)?;
+    let price = Money::new(price_line.get(..3)?, amount_in_line(price_line.get(4..)?)?);
+    let p = position.iter().position(|l| l.starts_with(value_title))?;
+    let value = Money::new(
+        position.get(p + 1)?.get(..3)?,
+        amount_in_line(position.get(p + 2)?)?,
+    );
+    Some(ViacHolding {
+        isin: position.get(1)?.to_string(),
+        share_title: lines.get(i.checked_sub(1)?)?.to_string(),
+        shares: amount_in_line(lines.get(i.checked_sub(shares_offset)?)?)?,
+        price,
+        value,
+    })
+}
+
 impl ViacPdfExtractor for ViacPdfGerman {
     fn filename(&self) -> String {
         self.0.filename()
@@ -215,78 +806,76 @@ impl ViacPdfExtractor for ViacPdfGerman {
         self.0.print_summary()
     }
 
-    fn document_type(&self) -> Result<ViacDocument, PdfError> {
-        if self.0.author != Some("VIAC".to_string()) {
-            Ok(ViacDocument::NotViac)
-        } else if self.0.pages[0].contains("Börsenabrechnung - Kauf") {
-            Ok(ViacDocument::Purchase(self.transaction()))
-        } else if self.0.pages[0].contains("Börsenabrechnung - Verkauf") {
-            Ok(ViacDocument::Sale(self.transaction()))
-        } else if self.0.pages[0].contains("Dividendenausschüttung") {
-            if self.0.pages[0].contains("Rückerstattung Quellensteuer") {
-                Ok(ViacDocument::TaxReturn(self.dividend()))
-            } else if self.0.pages[0].contains("Korrektur Dividendenausschüttung") {
-                Ok(ViacDocument::Unknown) // TODO: treat storno of dividends
-            } else {
-                Ok(ViacDocument::Dividend(self.dividend()))
-            }
-        } else if self.0.pages[0].contains("Verwaltungsgebühr") {
-            let f = ViacValuta {
-                valuta_price: self.valuta_price(),
-                valuta_date: self.valuta_date(),
-            };
-            Ok(ViacDocument::Fees(f))
-        } else if self.0.pages[0].contains("Zinsgutschrift") {
-            let f = ViacValuta {
-                valuta_price: self.interest_price(),
-                valuta_date: self.interest_date(),
-            };
-            Ok(ViacDocument::Interest(f))
-        } else if self.0.pages[0].contains("Zahlungseingang") {
-            let i = ViacValuta {
-                valuta_price: self.valuta_price(),
-                valuta_date: self.valuta_date(),
-            };
-            Ok(ViacDocument::Incoming(i))
-        } else if self.0.pages[0].contains("____impossible_____FeesRefund") {
-            Ok(ViacDocument::FeesRefund(0))
-        } else if self.0.pages[0].contains("____impossible_____InterestCharge") {
-            Ok(ViacDocument::InterestCharge(0))
-        } else if self.0.pages[0].contains("____impossible_____Outgoing") {
-            Ok(ViacDocument::Outgoing(0))
-        } else if self.0.pages[0].contains("____impossible_____Tax") {
-            Ok(ViacDocument::Tax(0))
-        } else if self.0.pages[0].contains("____impossible_____TransferIn") {
-            Ok(ViacDocument::TransferIn(0))
-        } else if self.0.pages[0].contains("____impossible_____TransferOut") {
-            Ok(ViacDocument::TransferOut(0))
-        } else if self.0.pages[0].contains("____impossible_____DeliveryIn") {
-            Ok(ViacDocument::DeliveryIn(0))
-        } else if self.0.pages[0].contains("____impossible_____DeliveryOut") {
-            Ok(ViacDocument::DeliveryOut(0))
-        } else {
-            Ok(ViacDocument::Unknown)
-        }
+    fn author(&self) -> Option<&str> {
+        self.0.author.as_deref()
+    }
+
+    fn first_page(&self) -> &str {
+        &self.0.pages[0]
+    }
+
+    fn document_kind(&self) -> Option<DocumentKind> {
+        classify(&self.filename(), self.first_page(), GERMAN_RULES).map(|r| r.kind)
+    }
+
+    fn check(&self, kind: DocumentKind) -> Result<(), ViacPdfError> {
+        self.0.check_guessed(
+            kind,
+            GuessedFields {
+                valuta_date: self.0.date_line(GERMAN_VALUTA).is_some(),
+                valuta_price: self.0.title_currency_amount("Valuta").is_some(),
+                isin: self.0.isin().is_some(),
+                share_title: self.0.line_near(|line| line == "Ant", 1).is_some(),
+                shares: self.german_shares().is_some(),
+                share_price: self.0.money_after_line("Kurs:").is_some(),
+            },
+        )
     }
 
     fn account_numbers(&self) -> (String, String) {
         self.0.account_numbers("Vertrag", "Portfolio")
     }
 
+    fn annual_statement(&self) -> Option<ViacAnnualStatement> {
+        Some(ViacAnnualStatement {
+            valuation_date: self.0.date_in_line(&["Vermögensausweis", "Jahresauszug"])?,
+            holdings: self.0.holdings(3, "Kurs:", "Bewertung")?,
+            cash: self
+                .0
+                .statement_currency_amount("Liquidität")
+                .unwrap_or(Money::new("CHF", Decimal::ZERO)),
+        })
+    }
+
+    fn counterparty(&self) -> String {
+        self.0
+            .line_after(&["Gegenportfolio", "Auftraggeber", "Begünstigter"])
+            .unwrap_or_default()
+    }
+
+    fn tax_statement(&self) -> Option<ViacTaxStatement> {
+        Some(ViacTaxStatement {
+            year: self.0.year_after("Steuerjahr")?,
+            contributions: self.0.title_currency_amount("Einbezahlte Beiträge"),
+            withholding_tax: self.0.title_currency_amount("Verrechnungssteuer"),
+            foreign_withholding_tax: self.0.title_currency_amount("Quellensteuer"),
+        })
+    }
+
+    fn product_type(&self) -> ProductType {
+        ProductType::from_markers(
+            self.first_page(),
+            &["Freizügigkeit"],
+            &["Säule 3a", "Vorsorgestiftung"],
+        )
+    }
+
     fn isin(&self) -> String {
-        self.0.isin()
+        self.0.isin().expect("no ISIN").to_string()
     }
 
     fn valuta_date(&self) -> NaiveDateTime {
-        for line in self.0.pages[0].lines() {
-            if line.starts_with("Valuta") {
-                return NaiveDate::parse_from_str(line, "Valuta %d.%m.%Y")
-                    .unwrap()
-                    .and_hms_opt(0, 0, 0)
-                    .unwrap();
-            }
-        }
-        unreachable!();
+        self.0.date_line(GERMAN_VALUTA).expect("no valuta date")
     }
 
     fn interest_date(&self) -> NaiveDateTime {
@@ -308,8 +897,17 @@ impl ViacPdfExtractor for ViacPdfGerman {
         self.0.title_currency_amount("Valuta").unwrap()
     }
 
-    fn taxes(&self) -> Option<Money> {
-        self.0.title_currency_amount("Stempelsteuer")
+    fn costs(&self) -> Vec<TradeCost> {
+        self.0.costs(&[
+            ("Stempelsteuer", CostKind::StampDuty),
+            ("Courtage", CostKind::Brokerage),
+            ("Fremdwährungsgebühr", CostKind::CurrencyExchange),
+            ("Übrige Gebühren", CostKind::Other),
+        ])
+    }
+
+    fn withholding_tax(&self) -> Option<Money> {
+        self.0.title_currency_amount("Quellensteuer")
     }
 
     fn exchange_rate_value(&self) -> Decimal {
@@ -342,11 +940,17 @@ impl ViacPdfExtractor for ViacPdfGerman {
     }
 
     fn share_price(&self) -> Money {
-        self.0.money_after_line("Kurs:")
+        self.0.money_after_line("Kurs:").expect("no share price")
     }
 
     fn dividend_price(&self) -> Money {
-        self.0.money_after_line("Ausschüttung:")
+        self.0
+            .money_after_line("Ausschüttung:")
+            .expect("no dividend price")
+    }
+
+    fn ex_date(&self) -> Option<NaiveDateTime> {
+        self.0.date_in_line(&["Ex-Datum"])
     }
 
     fn total_price(&self) -> Money {
@@ -358,25 +962,23 @@ impl ViacPdfExtractor for ViacPdfGerman {
     }
 
     fn shares(&self) -> Decimal {
-        let mut last_line = "";
-        for line in self.0.pages[0].lines() {
-            if line == "Ant" {
-                return Decimal::from_str(last_line).unwrap();
-            }
-            last_line = line;
-        }
-        unreachable!();
+        self.german_shares().expect("no shares")
     }
 
     fn share_title(&self) -> String {
-        let mut last_line = "";
-        for line in self.0.pages[0].lines() {
-            if last_line == "Ant" {
-                return line.to_string();
-            }
-            last_line = line;
-        }
-        unreachable!();
+        self.0
+            .line_near(|line| line == "Ant", 1)
+            .expect("no share title")
+            .to_string()
+    }
+}
+
+const GERMAN_VALUTA: &str = "Valuta %d.%m.%Y";
+
+impl ViacPdfGerman {
+    /// the number right above the unit `Ant`
+    fn german_shares(&self) -> Option<Decimal> {
+        Decimal::from_str(self.0.line_near(|line| line == "Ant", -1)?).ok()
     }
 }
 
@@ -388,60 +990,82 @@ impl ViacPdfExtractor for ViacPdfFrench {
         self.0.print_summary()
     }
 
-    fn document_type(&self) -> Result<ViacDocument, PdfError> {
-        if self.0.author != Some("VIAC".to_string()) {
-            Ok(ViacDocument::NotViac)
-        } else if self.0.pages[0].contains("Opération de bourse - Achat") {
-            Ok(ViacDocument::Purchase(self.transaction()))
-        } else if self.0.pages[0].contains("Opération de bourse - Vente") {
-            Ok(ViacDocument::Sale(self.transaction()))
-        } else if self.0.pages[0].contains("Avis de dividende") {
-            if self.0.pages[0].contains("Remboursement d'impôt à la source") {
-                Ok(ViacDocument::TaxReturn(self.dividend()))
-            } else {
-                Ok(ViacDocument::Dividend(self.dividend()))
-            }
-        } else if self.0.pages[0].contains("Commission") {
-            let f = ViacValuta {
-                valuta_price: self.valuta_price(),
-                valuta_date: self.valuta_date(),
-            };
-            Ok(ViacDocument::Fees(f))
-        } else if self.0.pages[0].contains("Intérêts") {
-            let f = ViacValuta {
-                valuta_price: self.interest_price(),
-                valuta_date: self.interest_date(),
-            };
-            Ok(ViacDocument::Interest(f))
-        } else if self.0.pages[0].contains("Avis de versement") {
-            let i = ViacValuta {
-                valuta_price: self.valuta_price(),
-                valuta_date: self.valuta_date(),
-            };
-            Ok(ViacDocument::Incoming(i))
-        } else {
-            Ok(ViacDocument::Unknown)
-        }
+    fn author(&self) -> Option<&str> {
+        self.0.author.as_deref()
+    }
+
+    fn first_page(&self) -> &str {
+        &self.0.pages[0]
+    }
+
+    fn document_kind(&self) -> Option<DocumentKind> {
+        classify(&self.filename(), self.first_page(), FRENCH_RULES).map(|r| r.kind)
+    }
+
+    fn check(&self, kind: DocumentKind) -> Result<(), ViacPdfError> {
+        self.0.check_guessed(
+            kind,
+            GuessedFields {
+                valuta_date: self.0.date_line(FRENCH_VALUTA).is_some(),
+                valuta_price: self.0.title_currency_amount("Valeur").is_some(),
+                isin: self.0.isin().is_some(),
+                share_title: self.0.line_near(|l| l.starts_with("ISIN:"), -1).is_some(),
+                shares: self.french_shares().is_some(),
+                share_price: self.0.money_after_line("Cours:").is_some(),
+            },
+        )
     }
 
     fn account_numbers(&self) -> (String, String) {
         self.0.account_numbers("Contrat", "Portefeuille")
     }
 
+    fn annual_statement(&self) -> Option<ViacAnnualStatement> {
+        Some(ViacAnnualStatement {
+            valuation_date: self
+                .0
+                .date_in_line(&["Relevé de fortune", "Relevé annuel"])?,
+            holdings: self.0.holdings(2, "Cours:", "Évaluation")?,
+            cash: self
+                .0
+                .statement_currency_amount("Liquidités")
+                .unwrap_or(Money::new("CHF", Decimal::ZERO)),
+        })
+    }
+
+    fn counterparty(&self) -> String {
+        self.0
+            .line_after(&[
+                "Portefeuille de contrepartie",
+                "Donneur d'ordre",
+                "Bénéficiaire",
+            ])
+            .unwrap_or_default()
+    }
+
+    fn tax_statement(&self) -> Option<ViacTaxStatement> {
+        Some(ViacTaxStatement {
+            year: self.0.year_after("Année fiscale")?,
+            contributions: self.0.title_currency_amount("Cotisations versées"),
+            withholding_tax: self.0.title_currency_amount("Impôt anticipé"),
+            foreign_withholding_tax: self.0.title_currency_amount("Impôt à la source"),
+        })
+    }
+
+    fn product_type(&self) -> ProductType {
+        ProductType::from_markers(
+            self.first_page(),
+            &["libre passage"],
+            &["pilier 3a", "Fondation de prévoyance"],
+        )
+    }
+
     fn isin(&self) -> String {
-        self.0.isin()
+        self.0.isin().expect("no ISIN").to_string()
     }
 
     fn valuta_date(&self) -> NaiveDateTime {
-        for line in self.0.pages[0].lines() {
-            if line.starts_with("Valeur") {
-                return NaiveDate::parse_from_str(line, "Valeur %d.%m.%Y")
-                    .unwrap()
-                    .and_hms_opt(0, 0, 0)
-                    .unwrap();
-            }
-        }
-        unreachable!();
+        self.0.date_line(FRENCH_VALUTA).expect("no valuta date")
     }
 
     fn interest_date(&self) -> NaiveDateTime {
@@ -463,8 +1087,17 @@ impl ViacPdfExtractor for ViacPdfFrench {
         self.0.title_currency_amount("Valeur").unwrap()
     }
 
-    fn taxes(&self) -> Option<Money> {
-        self.0.title_currency_amount("Droits de timbre")
+    fn costs(&self) -> Vec<TradeCost> {
+        self.0.costs(&[
+            ("Droits de timbre", CostKind::StampDuty),
+            ("Courtage", CostKind::Brokerage),
+            ("Frais de change", CostKind::CurrencyExchange),
+            ("Autres frais", CostKind::Other),
+        ])
+    }
+
+    fn withholding_tax(&self) -> Option<Money> {
+        self.0.title_currency_amount("Impôt à la source")
     }
 
     fn exchange_rate_value(&self) -> Decimal {
@@ -497,11 +1130,17 @@ impl ViacPdfExtractor for ViacPdfFrench {
     }
 
     fn share_price(&self) -> Money {
-        self.0.money_after_line("Cours:")
+        self.0.money_after_line("Cours:").expect("no share price")
     }
 
     fn dividend_price(&self) -> Money {
-        self.0.money_after_line("Dividende distribué:")
+        self.0
+            .money_after_line("Dividende distribué:")
+            .expect("no dividend price")
+    }
+
+    fn ex_date(&self) -> Option<NaiveDateTime> {
+        self.0.date_in_line(&["Date ex"])
     }
 
     fn total_price(&self) -> Money {
@@ -513,27 +1152,230 @@ impl ViacPdfExtractor for ViacPdfFrench {
     }
 
     fn shares(&self) -> Decimal {
-        let mut last_line = "";
-        let mut two_lines = "";
-        for line in self.0.pages[0].lines() {
-            if line.starts_with("ISIN:") {
-                return Decimal::from_str(two_lines).unwrap();
-            }
-            two_lines = last_line;
-            last_line = line;
-        }
-        unreachable!();
+        self.french_shares().expect("no shares")
     }
 
     fn share_title(&self) -> String {
-        let mut last_line = "";
-        for line in self.0.pages[0].lines() {
-            if line.starts_with("ISIN:") {
-                return last_line.to_string();
-            }
-            last_line = line;
+        self.0
+            .line_near(|line| line.starts_with("ISIN:"), -1)
+            .expect("no share title")
+            .to_string()
+    }
+}
+
+const FRENCH_VALUTA: &str = "Valeur %d.%m.%Y";
+
+impl ViacPdfFrench {
+    /// the number two lines above `ISIN:`
+    fn french_shares(&self) -> Option<Decimal> {
+        Decimal::from_str(self.0.line_near(|line| line.starts_with("ISIN:"), -2)?).ok()
+    }
+}
+
+impl ViacPdfTemplate {
+    /// `document_type` checks the fields a kind needs before extracting them,
+    /// and templates with statement rules are rejected when loading
+    fn missing(&self, field: &str) -> ! {
+        unreachable!(
+            "template {:?}: field {field} of {} was checked",
+            self.template.name,
+            self.data.filename()
+        )
+    }
+}
+
+impl ViacPdfExtractor for ViacPdfTemplate {
+    fn filename(&self) -> String {
+        self.data.filename()
+    }
+    fn print_summary(&self) {
+        self.data.print_summary()
+    }
+
+    fn author(&self) -> Option<&str> {
+        self.data.author.as_deref()
+    }
+
+    fn first_page(&self) -> &str {
+        &self.data.pages[0]
+    }
+
+    fn document_kind(&self) -> Option<DocumentKind> {
+        classify(&self.filename(), self.first_page(), &self.template.rules).map(|r| r.kind())
+    }
+
+    fn check(&self, kind: DocumentKind) -> Result<(), ViacPdfError> {
+        Ok(self.template.check(kind, self.first_page())?)
+    }
+
+    fn account_numbers(&self) -> (String, String) {
+        let f = &self.template.fields;
+        (
+            f.account.text(self.first_page()).unwrap_or_default(),
+            f.portfolio.text(self.first_page()).unwrap_or_default(),
+        )
+    }
+
+    fn annual_statement(&self) -> Option<ViacAnnualStatement> {
+        self.missing("annual_statement")
+    }
+
+    fn tax_statement(&self) -> Option<ViacTaxStatement> {
+        self.missing("tax_statement")
+    }
+
+    fn counterparty(&self) -> String {
+        self.template
+            .fields
+            .counterparty
+            .as_ref()
+            .and_then(|p| p.text(self.first_page()))
+            .unwrap_or_default()
+    }
+
+    fn product_type(&self) -> ProductType {
+        let t = &self.template;
+        let vested: Vec<&str> = t.vested_benefits.iter().map(String::as_str).collect();
+        let pillar_3a: Vec<&str> = t.pillar_3a.iter().map(String::as_str).collect();
+        ProductType::from_markers(self.first_page(), &vested, &pillar_3a)
+    }
+
+    fn isin(&self) -> String {
+        self.template
+            .fields
+            .isin
+            .as_ref()
+            .and_then(|p| p.text(self.first_page()))
+            .unwrap_or_else(|| self.missing("isin"))
+    }
+
+    fn valuta_date(&self) -> NaiveDateTime {
+        self.template
+            .fields
+            .date
+            .date(self.first_page())
+            .unwrap_or_else(|| self.missing("date"))
+    }
+
+    fn interest_date(&self) -> NaiveDateTime {
+        match &self.template.fields.interest_date {
+            Some(p) => p.date(self.first_page()),
+            None => self.template.fields.date.date(self.first_page()),
         }
-        unreachable!();
+        .unwrap_or_else(|| self.missing("interest_date"))
+    }
+
+    fn valuta_price(&self) -> Money {
+        self.template
+            .fields
+            .valuta
+            .money(self.first_page())
+            .unwrap_or_else(|| self.missing("valuta"))
+    }
+
+    fn costs(&self) -> Vec<TradeCost> {
+        let fields = &self.template.fields;
+        let stamp_duty = fields.taxes.as_ref().map(|p| (CostKind::StampDuty, p));
+        stamp_duty
+            .into_iter()
+            .chain(fields.costs.iter().map(|c| (c.kind, &c.pattern)))
+            .filter_map(|(kind, p)| {
+                p.money(self.first_page())
+                    .map(|amount| TradeCost { kind, amount })
+            })
+            .collect()
+    }
+
+    fn withholding_tax(&self) -> Option<Money> {
+        self.template
+            .fields
+            .withholding_tax
+            .as_ref()
+            .and_then(|p| p.money(self.first_page()))
+    }
+
+    fn exchange_rate_value(&self) -> Decimal {
+        self.template
+            .fields
+            .exchange_rate
+            .as_ref()
+            .and_then(|p| p.decimal(self.first_page(), "rate"))
+            .unwrap_or_else(|| self.missing("exchange_rate"))
+    }
+
+    fn exchange_rate(&self) -> Option<ExchangeRate> {
+        self.template
+            .fields
+            .exchange_total
+            .as_ref()
+            .and_then(|p| p.money(self.first_page()))
+            .map(|chf_total| ExchangeRate {
+                rate: self.exchange_rate_value(),
+                total_price: self.total_price(),
+                pdf_price: chf_total,
+            })
+    }
+
+    fn share_price(&self) -> Money {
+        self.template
+            .fields
+            .price
+            .as_ref()
+            .and_then(|p| p.money(self.first_page()))
+            .unwrap_or_else(|| self.missing("price"))
+    }
+
+    fn dividend_price(&self) -> Money {
+        self.template
+            .fields
+            .dividend
+            .as_ref()
+            .and_then(|p| p.money(self.first_page()))
+            .unwrap_or_else(|| self.missing("dividend"))
+    }
+
+    fn ex_date(&self) -> Option<NaiveDateTime> {
+        self.template
+            .fields
+            .ex_date
+            .as_ref()
+            .and_then(|p| p.date(self.first_page()))
+    }
+
+    fn total_price(&self) -> Money {
+        self.template
+            .fields
+            .total
+            .as_ref()
+            .and_then(|p| p.money(self.first_page()))
+            .unwrap_or_else(|| self.missing("total"))
+    }
+
+    fn interest_price(&self) -> Money {
+        self.template
+            .fields
+            .interest
+            .as_ref()
+            .and_then(|p| p.money(self.first_page()))
+            .unwrap_or_else(|| self.missing("interest"))
+    }
+
+    fn shares(&self) -> Decimal {
+        self.template
+            .fields
+            .shares
+            .as_ref()
+            .and_then(|p| p.decimal(self.first_page(), "value"))
+            .unwrap_or_else(|| self.missing("shares"))
+    }
+
+    fn share_title(&self) -> String {
+        self.template
+            .fields
+            .share_title
+            .as_ref()
+            .and_then(|p| p.text(self.first_page()))
+            .unwrap_or_else(|| self.missing("share_title"))
     }
 }
 
@@ -545,17 +1387,61 @@ pub enum ViacDocument {
     Sale(ViacTransaction),
     Dividend(ViacDividend),
     Fees(ViacValuta),
-    FeesRefund(i32),
+    FeesRefund(ViacValuta),
     Interest(ViacValuta),
-    InterestCharge(i32),
+    InterestCharge(ViacValuta),
     Incoming(ViacValuta),
-    Outgoing(i32),
-    Tax(i32),
     TaxReturn(ViacDividend),
-    TransferIn(i32),
-    TransferOut(i32),
-    DeliveryIn(i32),
-    DeliveryOut(i32),
+    TransferIn(ViacTransfer),
+    TransferOut(ViacTransfer),
+    DeliveryIn(ViacDelivery),
+    DeliveryOut(ViacDelivery),
+    AnnualStatement(ViacAnnualStatement),
+    /// Steuerbescheinigung 3a, the contributions paid in a year
+    ContributionCertificate(ViacTaxStatement),
+    /// Steuerauszug, withholding taxes of a year
+    TaxStatement(ViacTaxStatement),
+}
+
+#[derive(Debug)]
+pub struct ViacTaxStatement {
+    pub year: i32,
+    pub contributions: Option<Money>,
+    /// Verrechnungssteuer, the swiss withholding tax
+    pub withholding_tax: Option<Money>,
+    /// Quellensteuer deducted abroad
+    pub foreign_withholding_tax: Option<Money>,
+}
+
+impl ViacTaxStatement {
+    /// statements are dated to the end of their year
+    pub fn date(&self) -> NaiveDateTime {
+        midnight(self.year, 12, 31)
+    }
+}
+
+/// start of a calendar day, documents are dated to the day only
+pub fn midnight(year: i32, month: u32, day: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .unwrap_or_else(|| panic!("no such date {year}-{month}-{day}"))
+}
+
+/// year end positions as stated by VIAC, checkpoints for the positions computed from transactions
+#[derive(Debug)]
+pub struct ViacAnnualStatement {
+    pub valuation_date: NaiveDateTime,
+    pub holdings: Vec<ViacHolding>,
+    pub cash: Money,
+}
+
+#[derive(Debug)]
+pub struct ViacHolding {
+    pub isin: String,
+    pub share_title: String,
+    pub shares: Decimal,
+    pub price: Money,
+    pub value: Money,
 }
 
 #[derive(Debug)]
@@ -568,6 +1454,10 @@ pub struct ViacDividend {
     dividend_price: Money,
     total_price: Money,
     exchange_rate: Option<ExchangeRate>,
+    /// foreign tax deducted before the dividend was paid out, in the dividend currency
+    withholding_tax: Option<Money>,
+    /// not every dividend PDF states it, refunds are matched by valuta date then
+    pub ex_date: Option<NaiveDateTime>,
 }
 
 impl ViacDividend {
@@ -582,6 +1472,27 @@ impl ViacDividend {
         );
         self.total_price.amount / self.dividend_price.amount
     }
+
+    /// withholding tax in the currency the dividend was booked in
+    pub fn withholding_tax_booked(&self) -> Option<Money> {
+        let tax = self.withholding_tax?;
+        if tax.currency == self.valuta_price.currency {
+            return Some(Money {
+                currency: tax.currency,
+                amount: tax.amount.abs(),
+            });
+        }
+        match &self.exchange_rate {
+            Some(rate) => Some(Money {
+                currency: self.valuta_price.currency,
+                amount: (tax.amount.abs() * rate.rate).round_dp(2),
+            }),
+            None => {
+                warn!("withholding tax {tax} not in the booked currency and no exchange rate, ignoring it");
+                None
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -590,11 +1501,84 @@ pub struct ViacValuta {
     valuta_price: Money,
 }
 
+/// shares moved in kind into or out of a portfolio
+#[derive(Debug)]
+pub struct ViacDelivery {
+    valuta_date: NaiveDateTime,
+    isin: String,
+    share_title: String,
+    shares: Decimal,
+    /// reference price the delivery is valued at
+    share_price: Money,
+    /// CHF per unit of the reference price currency, deliveries state none,
+    /// foreign ones take the rate of a trade, see `holdings::rate_deliveries`
+    pub chf_rate: Option<Decimal>,
+}
+
+impl ViacDelivery {
+    pub fn value(&self) -> Money {
+        Money {
+            currency: self.share_price.currency,
+            amount: (self.shares * self.share_price.amount).round_dp(2),
+        }
+    }
+
+    /// value in CHF, the foreign value while no rate is known
+    pub fn value_booked(&self) -> Money {
+        match self.chf_rate {
+            Some(rate) => Money {
+                currency: CHF,
+                amount: (self.value().amount * rate).round_dp(2),
+            },
+            None => self.value(),
+        }
+    }
+}
+
+/// money moved between VIAC portfolios or from/to another pension foundation
+#[derive(Debug)]
+pub struct ViacTransfer {
+    valuta_date: NaiveDateTime,
+    valuta_price: Money,
+    /// portfolio number or name of the foundation on the other side
+    pub counterparty: String,
+}
+
+/// VIAC runs pillar 3a and vested benefits portfolios, the documents name their foundation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductType {
+    Pillar3a,
+    VestedBenefits,
+    Unknown,
+}
+
+impl ProductType {
+    fn from_markers(text: &str, vested_benefits: &[&str], pillar_3a: &[&str]) -> Self {
+        if vested_benefits.iter().any(|m| text.contains(m)) {
+            ProductType::VestedBenefits
+        } else if pillar_3a.iter().any(|m| text.contains(m)) {
+            ProductType::Pillar3a
+        } else {
+            ProductType::Unknown
+        }
+    }
+
+    /// name of the PP account and prefix of the exported files, e.g. `VIAC_3a_1234`
+    pub fn account_name(&self, portfolio_number: &str) -> String {
+        match self {
+            ProductType::Pillar3a => format!("VIAC_3a_{portfolio_number}"),
+            ProductType::VestedBenefits => format!("VIAC_FZ_{portfolio_number}"),
+            ProductType::Unknown => format!("VIAC_{portfolio_number}"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ViacSummary {
     deduce: bool,
     pub account_number: String,
     pub portfolio_number: String,
+    pub product_type: ProductType,
     pub comment: String,
     pub document_type: ViacDocument,
 }
@@ -602,15 +1586,48 @@ pub struct ViacSummary {
 impl ViacSummary {
     pub fn valuta_date(&self) -> NaiveDateTime {
         match &self.document_type {
-            ViacDocument::Interest(s) | ViacDocument::Fees(s) | ViacDocument::Incoming(s) => {
-                s.valuta_date
-            }
+            ViacDocument::Interest(s)
+            | ViacDocument::InterestCharge(s)
+            | ViacDocument::Fees(s)
+            | ViacDocument::FeesRefund(s)
+            | ViacDocument::Incoming(s) => s.valuta_date,
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s.valuta_date,
             ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => s.valuta_date,
+            ViacDocument::TransferIn(s) | ViacDocument::TransferOut(s) => s.valuta_date,
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s) => s.valuta_date,
+            ViacDocument::AnnualStatement(s) => s.valuation_date,
+            ViacDocument::ContributionCertificate(s) | ViacDocument::TaxStatement(s) => s.date(),
             _ => unreachable!(),
         }
     }
 
+    /// documents booked on the cash account of the portfolio
+    pub fn is_account_transaction(&self) -> bool {
+        matches!(
+            self.document_type,
+            ViacDocument::Interest(_)
+                | ViacDocument::InterestCharge(_)
+                | ViacDocument::Fees(_)
+                | ViacDocument::FeesRefund(_)
+                | ViacDocument::Incoming(_)
+                | ViacDocument::Dividend(_)
+                | ViacDocument::TaxReturn(_)
+                | ViacDocument::TransferIn(_)
+                | ViacDocument::TransferOut(_)
+        )
+    }
+
+    /// documents booked on the securities account of the portfolio
+    pub fn is_portfolio_transaction(&self) -> bool {
+        matches!(
+            self.document_type,
+            ViacDocument::Purchase(_)
+                | ViacDocument::Sale(_)
+                | ViacDocument::DeliveryIn(_)
+                | ViacDocument::DeliveryOut(_)
+        )
+    }
+
     /// from name.abuchen.portfolio.model.AccountTransaction enum Type
     pub fn order_type(&self) -> String {
         match &self.document_type {
@@ -619,12 +1636,10 @@ impl ViacSummary {
             ViacDocument::Fees(_) => "FEES",         //"Gebühren",
             ViacDocument::FeesRefund(_) => "FEES_REFUND", //"Gebührenrückerstattung",
             ViacDocument::Incoming(_) => "DEPOSIT",  //"Einlage",
-            ViacDocument::Outgoing(_) => "REMOVAL",  //"Entnahme",
             ViacDocument::Purchase(_) => "BUY",      //"Kauf",
             ViacDocument::Sale(_) => "SELL",         // "Verkauf",
             ViacDocument::Dividend(_) => "DIVIDENDS", //"Dividende",
             ViacDocument::TaxReturn(_) => "TAX_REFUND", //"Steuerrückerstattung",
-            ViacDocument::Tax(_) => "TAXES",         //"Steuern",
             ViacDocument::TransferIn(_) => "TRANSFER_IN", //"Umbuchung (Eingang)",
             ViacDocument::TransferOut(_) => "TRANSFER_OUT", //"Umbuchung (Ausgang)",
             ViacDocument::DeliveryIn(_) => "DELIVERY_INBOUND", //"Einlieferung",
@@ -636,11 +1651,15 @@ impl ViacSummary {
 
     pub fn valuta_price(&self) -> (String, String) {
         let v = match &self.document_type {
-            ViacDocument::Interest(s) | ViacDocument::Fees(s) | ViacDocument::Incoming(s) => {
-                s.valuta_price
-            }
+            ViacDocument::Interest(s)
+            | ViacDocument::InterestCharge(s)
+            | ViacDocument::Fees(s)
+            | ViacDocument::FeesRefund(s)
+            | ViacDocument::Incoming(s) => s.valuta_price,
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s.valuta_price,
             ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => s.valuta_price,
+            ViacDocument::TransferIn(s) | ViacDocument::TransferOut(s) => s.valuta_price,
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s) => s.value_booked(),
             _ => unreachable!(),
         };
         (
@@ -651,9 +1670,13 @@ impl ViacSummary {
 
     pub fn total_price(&self, conversion_rate: Decimal) -> (String, String) {
         match &self.document_type {
-            ViacDocument::Interest(_) | ViacDocument::Fees(_) | ViacDocument::Incoming(_) => {
-                ("".to_owned(), "".to_owned())
-            }
+            ViacDocument::Interest(_)
+            | ViacDocument::InterestCharge(_)
+            | ViacDocument::Fees(_)
+            | ViacDocument::FeesRefund(_)
+            | ViacDocument::Incoming(_)
+            | ViacDocument::TransferIn(_)
+            | ViacDocument::TransferOut(_) => ("".to_owned(), "".to_owned()),
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => (
                 (s.total_price.amount * conversion_rate).to_string(),
                 std::str::from_utf8(&s.total_price.currency)
@@ -666,6 +1689,12 @@ impl ViacSummary {
                     .unwrap()
                     .to_string(),
             ),
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s) => (
+                (s.value().amount * conversion_rate).to_string(),
+                std::str::from_utf8(&s.share_price.currency)
+                    .unwrap()
+                    .to_string(),
+            ),
             _ => unreachable!(),
         }
     }
@@ -673,9 +1702,11 @@ impl ViacSummary {
     /// VIAC documents are rounded to 2 decimals, exchange rate is therefore not making PP happy, compute it
     pub fn exchange_rate_compute(&self, conversion_rate: Decimal) -> String {
         let v = match &self.document_type {
-            ViacDocument::Interest(s) | ViacDocument::Fees(s) | ViacDocument::Incoming(s) => {
-                s.valuta_price
-            }
+            ViacDocument::Interest(s)
+            | ViacDocument::InterestCharge(s)
+            | ViacDocument::Fees(s)
+            | ViacDocument::FeesRefund(s)
+            | ViacDocument::Incoming(s) => s.valuta_price,
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s.valuta_price,
             ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => s.valuta_price,
             _ => unreachable!(),
@@ -690,6 +1721,15 @@ impl ViacSummary {
             .to_string()
     }
 
+    /// conversion VIAC applied to a trade or dividend in foreign currency
+    pub fn exchange(&self) -> Option<&ExchangeRate> {
+        match &self.document_type {
+            ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s.exchange_rate.as_ref(),
+            ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => s.exchange_rate.as_ref(),
+            _ => None,
+        }
+    }
+
     pub fn exchange_rate(&self, conversion_rate: Decimal) -> String {
         match &self.document_type {
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s
@@ -700,32 +1740,81 @@ impl ViacSummary {
                 .exchange_rate
                 .as_ref()
                 .map_or("".to_owned(), |x| (x.rate * conversion_rate).to_string()),
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s)
+                if s.share_price.currency != CHF =>
+            {
+                s.chf_rate
+                    .map_or("".to_owned(), |rate| (rate * conversion_rate).to_string())
+            }
             _ => "".to_owned(),
         }
     }
     pub fn fees(&self) -> String {
         match &self.document_type {
             ViacDocument::Fees(s) => s.valuta_price.amount.to_string(),
+            ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s
+                .fees()
+                .map_or("0.00".to_string(), |f| f.amount.to_string()),
             _ => "0.00".to_string(),
         }
     }
     pub fn taxes(&self) -> String {
         match &self.document_type {
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s
-                .taxes
-                .as_ref()
+                .taxes()
+                .map_or("0.00".to_string(), |t| t.amount.to_string()),
+            ViacDocument::Dividend(s) => s
+                .withholding_tax_booked()
                 .map_or("0.00".to_string(), |t| t.amount.to_string()),
             _ => "0.00".to_string(),
         }
     }
+    /// signed effect on the cash account, None for documents not moving cash
+    pub fn cash_flow(&self) -> Option<Money> {
+        match &self.document_type {
+            ViacDocument::Interest(s) | ViacDocument::Incoming(s) => Some(s.valuta_price),
+            ViacDocument::Fees(s) | ViacDocument::InterestCharge(s) => Some(-s.valuta_price),
+            ViacDocument::FeesRefund(s) => Some(s.valuta_price),
+            ViacDocument::Purchase(s) => Some(-s.valuta_price),
+            ViacDocument::Sale(s) => Some(s.valuta_price),
+            ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => Some(s.valuta_price),
+            ViacDocument::TransferIn(s) => Some(s.valuta_price),
+            ViacDocument::TransferOut(s) => Some(-s.valuta_price),
+            _ => None,
+        }
+    }
+
+    /// signed change of the shares held, None for documents not moving shares
+    pub fn share_delta(&self) -> Option<(&str, Decimal)> {
+        match &self.document_type {
+            ViacDocument::Purchase(s) => Some((&s.isin, self.transaction_shares(s))),
+            ViacDocument::Sale(s) => Some((&s.isin, -self.transaction_shares(s))),
+            ViacDocument::DeliveryIn(s) => Some((&s.isin, s.shares)),
+            ViacDocument::DeliveryOut(s) => Some((&s.isin, -s.shares)),
+            _ => None,
+        }
+    }
+
+    fn transaction_shares(&self, t: &ViacTransaction) -> Decimal {
+        if self.deduce {
+            t.real_shares_count().round_dp(5)
+        } else {
+            t.shares
+        }
+    }
+
+    /// portfolio or foundation on the other side of a transfer
+    pub fn counterparty(&self) -> Option<&str> {
+        match &self.document_type {
+            ViacDocument::TransferIn(s) | ViacDocument::TransferOut(s) => Some(&s.counterparty),
+            _ => None,
+        }
+    }
+
     pub fn shares(&self) -> String {
         match &self.document_type {
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => {
-                if self.deduce {
-                    s.real_shares_count().round_dp(5).to_string()
-                } else {
-                    s.shares.to_string()
-                }
+                self.transaction_shares(s).to_string()
             }
             ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => {
                 if self.deduce {
@@ -734,6 +1823,7 @@ impl ViacSummary {
                     s.shares.to_string()
                 }
             }
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s) => s.shares.to_string(),
             _ => "0.00".to_string(),
         }
     }
@@ -741,6 +1831,7 @@ impl ViacSummary {
         match &self.document_type {
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s.isin.to_owned(),
             ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => s.isin.to_owned(),
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s) => s.isin.to_owned(),
             _ => "".to_string(),
         }
     }
@@ -748,6 +1839,7 @@ impl ViacSummary {
         match &self.document_type {
             ViacDocument::Purchase(s) | ViacDocument::Sale(s) => s.share_title.to_owned(),
             ViacDocument::Dividend(s) | ViacDocument::TaxReturn(s) => s.share_title.to_owned(),
+            ViacDocument::DeliveryIn(s) | ViacDocument::DeliveryOut(s) => s.share_title.to_owned(),
             _ => "".to_string(),
         }
     }
@@ -760,7 +1852,7 @@ pub struct ViacTransaction {
     share_price: Money,
     total_price: Money,
     valuta_price: Money,
-    taxes: Option<Money>,
+    costs: Vec<TradeCost>,
     isin: String,
     share_title: String,
     exchange_rate: Option<ExchangeRate>,
@@ -774,6 +1866,16 @@ pub struct ExchangeRate {
 }
 
 impl ExchangeRate {
+    /// CHF per unit of the foreign currency
+    pub fn rate(&self) -> Decimal {
+        self.rate
+    }
+
+    /// the amount converted, in foreign currency
+    pub fn foreign(&self) -> Money {
+        self.total_price
+    }
+
     /// If exchange_rate is given we can use it compute a total_price with more decimal digits
     pub fn total_price_chf(&self) -> Money {
         assert_ne!(self.total_price.currency, crate::money::CHF);
@@ -781,13 +1883,90 @@ impl ExchangeRate {
     }
 }
 
+/// The cost lines VIAC lists on a trade confirmation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CostKind {
+    /// Stempelsteuer / Droits de timbre, exported as taxes
+    StampDuty,
+    Brokerage,
+    CurrencyExchange,
+    Other,
+}
+
+#[derive(Debug)]
+pub struct TradeCost {
+    pub kind: CostKind,
+    pub amount: Money,
+}
+
 impl ViacTransaction {
-    pub fn valuta_without_taxes(&self) -> Money {
-        match &self.taxes {
-            Some(taxes) => {
-                assert_eq!(self.valuta_price.currency, taxes.currency);
-                Money::new("CHF", self.valuta_price.amount - taxes.amount)
+    /// a cost line in the currency the trade was booked in,
+    /// None when it is in another currency than the trade's and no exchange rate converts it
+    pub fn cost_booked(&self, cost: &TradeCost) -> Option<Money> {
+        if cost.amount.currency == self.valuta_price.currency {
+            return Some(cost.amount);
+        }
+        match &self.exchange_rate {
+            Some(rate) if rate.total_price.currency == cost.amount.currency => Some(Money {
+                currency: self.valuta_price.currency,
+                amount: (cost.amount.amount * rate.rate).round_dp(2),
+            }),
+            _ => {
+                warn!(
+                    "cost {} not in the booked currency and no exchange rate, ignoring it",
+                    cost.amount
+                );
+                None
             }
+        }
+    }
+
+    /// sum of the cost lines selected in the booked currency, None when the trade has none
+    fn costs_sum(&self, selected: impl Fn(CostKind) -> bool) -> Option<Money> {
+        self.costs
+            .iter()
+            .filter(|c| selected(c.kind))
+            .filter_map(|c| self.cost_booked(c))
+            .reduce(|sum, c| Money {
+                currency: sum.currency,
+                amount: sum.amount + c.amount,
+            })
+    }
+
+    pub fn costs(&self) -> &[TradeCost] {
+        &self.costs
+    }
+
+    pub fn taxes(&self) -> Option<Money> {
+        self.costs_sum(|k| k == CostKind::StampDuty)
+    }
+
+    pub fn fees(&self) -> Option<Money> {
+        self.costs_sum(|k| k != CostKind::StampDuty)
+    }
+
+    pub fn share_price(&self) -> Money {
+        self.share_price
+    }
+
+    /// amount settled on the account, including costs
+    pub fn valuta_price(&self) -> Money {
+        self.valuta_price
+    }
+
+    /// CHF per unit of the trade currency, one for trades in CHF
+    pub fn chf_rate(&self) -> Decimal {
+        self.exchange_rate
+            .as_ref()
+            .map_or(Decimal::ONE, |er| er.rate)
+    }
+
+    pub fn valuta_without_costs(&self) -> Money {
+        match self.costs_sum(|_| true) {
+            Some(costs) => Money {
+                currency: self.valuta_price.currency,
+                amount: self.valuta_price.amount - costs.amount,
+            },
             None => self.valuta_price,
         }
     }
@@ -822,3 +2001,445 @@ impl ViacTransaction {
         }
     }
 }
+
+/// documents built from page text or in code for the tests
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// first page of a dividend paid in USD, withholding tax deducted abroad
+    pub const GERMAN_DIVIDEND: &str = "Dividendenausschüttung\nVertrag\n1000\nPortfolio\n1\n\
+        12.5\nAnt\nUBS ETF MSCI World\nISIN:\nIE00B4L5Y983\nEx-Datum 10.05.2023\n\
+        Ausschüttung:\nUSD 0.80\nBetrag\nUSD\n10.00\n\
+        Quellensteuer gemäss Doppelbesteuerungsabkommen\nBetrag abzüglich Steuern\n\
+        Quellensteuer\nUSD\n-1.50\nUmrechnungskurs USD/CHF 0.90\nCHF\n7.65\n\
+        Valuta 15.05.2023\nCHF\n7.65";
+
+    fn data(page: &str) -> ViacPdfData {
+        ViacPdfData {
+            path: PathBuf::from("test.pdf"),
+            title: None,
+            author: Some("VIAC".to_string()),
+            pages: vec![page.to_string()],
+        }
+    }
+
+    pub fn german(page: &str) -> ViacPdfGerman {
+        ViacPdfGerman(data(page))
+    }
+
+    pub fn french(page: &str) -> ViacPdfFrench {
+        ViacPdfFrench(data(page))
+    }
+
+    /// `CHF 1'000.00`
+    pub fn money(s: &str) -> Money {
+        let (currency, amount) = s.split_once(' ').unwrap();
+        Money::new(currency, amount_in_line(amount).unwrap())
+    }
+
+    /// a document of portfolio `1` in contract `1000`
+    pub fn summary(document_type: ViacDocument) -> ViacSummary {
+        ViacSummary {
+            deduce: false,
+            account_number: "1000".to_string(),
+            portfolio_number: "1".to_string(),
+            product_type: ProductType::Pillar3a,
+            comment: "viac_pdf_import test.pdf".to_string(),
+            document_type,
+        }
+    }
+
+    /// a deposit, fee, interest or the like
+    pub fn cash(valuta_date: NaiveDateTime, amount: &str) -> ViacValuta {
+        ViacValuta {
+            valuta_date,
+            valuta_price: money(amount),
+        }
+    }
+
+    /// a trade in CHF without costs
+    pub fn trade(
+        valuta_date: NaiveDateTime,
+        isin: &str,
+        shares: &str,
+        price: &str,
+    ) -> ViacTransaction {
+        let shares = Decimal::from_str(shares).unwrap();
+        let share_price = money(price);
+        let total_price = Money {
+            currency: share_price.currency,
+            amount: shares * share_price.amount,
+        };
+        ViacTransaction {
+            valuta_date,
+            shares,
+            share_price,
+            total_price,
+            valuta_price: total_price,
+            costs: vec![],
+            isin: isin.to_string(),
+            share_title: format!("Fund {isin}"),
+            exchange_rate: None,
+        }
+    }
+
+    /// positions as (ISIN, shares, price)
+    pub fn statement(
+        valuation_date: NaiveDateTime,
+        positions: &[(&str, &str, &str)],
+        cash: &str,
+    ) -> ViacAnnualStatement {
+        ViacAnnualStatement {
+            valuation_date,
+            holdings: positions
+                .iter()
+                .map(|(isin, shares, price)| {
+                    let shares = Decimal::from_str(shares).unwrap();
+                    let price = money(price);
+                    ViacHolding {
+                        isin: isin.to_string(),
+                        share_title: format!("Fund {isin}"),
+                        shares,
+                        price,
+                        value: Money {
+                            currency: price.currency,
+                            amount: shares * price.amount,
+                        },
+                    }
+                })
+                .collect(),
+            cash: money(cash),
+        }
+    }
+
+    /// a trade in a foreign currency, booked in CHF at `rate`
+    pub fn foreign_trade(
+        valuta_date: NaiveDateTime,
+        isin: &str,
+        shares: &str,
+        price: &str,
+        rate: &str,
+    ) -> ViacTransaction {
+        let mut t = trade(valuta_date, isin, shares, price);
+        let rate = Decimal::from_str(rate).unwrap();
+        t.valuta_price = Money::new("CHF", (t.total_price.amount * rate).round_dp(2));
+        t.exchange_rate = Some(ExchangeRate {
+            rate,
+            total_price: t.total_price,
+            pdf_price: t.valuta_price,
+        });
+        t
+    }
+
+    /// a purchase paying `cost` on top of its settled amount
+    pub fn with_cost(mut t: ViacTransaction, kind: CostKind, cost: &str) -> ViacTransaction {
+        let amount = money(cost);
+        t.valuta_price.amount += amount.amount;
+        t.costs.push(TradeCost { kind, amount });
+        t
+    }
+
+    pub fn delivery(
+        valuta_date: NaiveDateTime,
+        isin: &str,
+        shares: &str,
+        price: &str,
+    ) -> ViacDelivery {
+        let share_price = money(price);
+        ViacDelivery {
+            valuta_date,
+            isin: isin.to_string(),
+            share_title: format!("Fund {isin}"),
+            shares: Decimal::from_str(shares).unwrap(),
+            chf_rate: (share_price.currency == CHF).then_some(Decimal::ONE),
+            share_price,
+        }
+    }
+
+    pub fn transfer(valuta_date: NaiveDateTime, amount: &str, counterparty: &str) -> ViacTransfer {
+        ViacTransfer {
+            valuta_date,
+            valuta_price: money(amount),
+            counterparty: counterparty.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::*;
+    use super::*;
+    use crate::classify::classify;
+
+    #[test]
+    fn statements_listing_fees_and_interest_stay_statements() {
+        let text = "Vermögensausweis\nVerwaltungsgebühr -12.30\nZinsgutschrift 0.40";
+        let rule = classify("t.pdf", text, GERMAN_RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::AnnualStatement);
+        let text = "Relevé fiscal\nCommission -12.30\nIntérêts 0.40";
+        let rule = classify("t.pdf", text, FRENCH_RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::TaxStatement);
+    }
+
+    #[test]
+    fn documents_mentioning_a_statement_keep_their_kind() {
+        let text =
+            "Zahlungseingang\nBetrag\nCHF\n1'000.00\nDie Steuerbescheinigung folgt im Januar.";
+        let rule = classify("t.pdf", text, GERMAN_RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::Incoming);
+        let text = "Steuerbescheinigung Säule 3a\nZahlungseingang 2023";
+        let rule = classify("t.pdf", text, GERMAN_RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::ContributionCertificate);
+        let text = "Commission de gestion\nVoir l'Attestation fiscale de l'année.";
+        let rule = classify("t.pdf", text, FRENCH_RULES).unwrap();
+        assert_eq!(rule.kind, DocumentKind::Fees);
+    }
+
+    #[test]
+    fn dividend_withholding_tax_is_booked_in_chf() {
+        let summary = german(GERMAN_DIVIDEND).summary(false).unwrap();
+        let ViacDocument::Dividend(d) = &summary.document_type else {
+            panic!("not a dividend");
+        };
+        assert_eq!(d.withholding_tax.unwrap().to_string(), "USD -1.50");
+        assert_eq!(d.total_price.to_string(), "USD 10.00");
+        assert_eq!(d.valuta_price.to_string(), "CHF 7.65");
+        assert_eq!(d.ex_date, Some(midnight(2023, 5, 10)));
+        assert_eq!(summary.taxes(), "1.35");
+    }
+
+    #[test]
+    fn deliveries_and_transfers_read_from_the_page() {
+        let page = "Einlieferung\n12.5\nAnt\nUBS ETF MSCI World\nISIN:\nIE00B4L5Y983\n\
+                    Kurs:\nUSD 80.50\nValuta 02.05.2023";
+        let ViacDocument::DeliveryIn(d) = german(page).document_type().unwrap() else {
+            panic!("not a delivery");
+        };
+        assert_eq!(d.valuta_date, midnight(2023, 5, 2));
+        assert_eq!(d.isin, "IE00B4L5Y983");
+        assert_eq!(d.share_title, "UBS ETF MSCI World");
+        assert_eq!(d.shares.to_string(), "12.5");
+        assert_eq!(d.share_price.to_string(), "USD 80.50");
+        assert!(d.chf_rate.is_none());
+
+        let page = "Sortie de titres\nUBS ETF SMI\n3\nUBS ETF SMI\nISIN:\nCH0017142719\n\
+                    Cours:\nCHF 110.00\nValeur 03.05.2023";
+        let ViacDocument::DeliveryOut(d) = french(page).document_type().unwrap() else {
+            panic!("not a delivery");
+        };
+        assert_eq!(d.valuta_date, midnight(2023, 5, 3));
+        assert_eq!(d.shares.to_string(), "3");
+        assert_eq!(d.share_price.to_string(), "CHF 110.00");
+        assert_eq!(d.chf_rate, Some(Decimal::ONE));
+
+        let page =
+            "Transfert sortant\nPortefeuille de contrepartie\n2\nValeur 02.05.2023\nCHF\n1'000.00";
+        let ViacDocument::TransferOut(t) = french(page).document_type().unwrap() else {
+            panic!("not a transfer");
+        };
+        assert_eq!(t.valuta_date, midnight(2023, 5, 2));
+        assert_eq!(t.valuta_price.to_string(), "CHF 1000.00");
+        assert_eq!(t.counterparty, "2");
+    }
+
+    #[test]
+    fn fee_refunds_and_interest_charges_read_from_the_page() {
+        let page = "Rückerstattung Verwaltungsgebühr\nValuta 31.03.2023\nCHF\n4.20";
+        let ViacDocument::FeesRefund(v) = german(page).document_type().unwrap() else {
+            panic!("not a fee refund");
+        };
+        assert_eq!(v.valuta_date, midnight(2023, 3, 31));
+        assert_eq!(v.valuta_price.to_string(), "CHF 4.20");
+        let page = "Intérêts négatifs\nValeur 30.06.2023\nCHF\n0.35";
+        let summary = french(page).summary(false).unwrap();
+        assert!(matches!(
+            summary.document_type,
+            ViacDocument::InterestCharge(_)
+        ));
+        assert_eq!(summary.cash_flow().unwrap().to_string(), "CHF -0.35");
+        let page = "Remboursement de commission\nCHF\n4.20";
+        assert!(matches!(
+            french(page).document_type(),
+            Err(ViacPdfError::MissingField(
+                _,
+                DocumentKind::FeesRefund,
+                "valuta date"
+            ))
+        ));
+    }
+
+    #[test]
+    fn guessed_layouts_missing_a_field_are_errors() {
+        let page = "Eingang Übertrag\nGegenportfolio\n1\nValuta\nCHF\n1'000.00";
+        assert!(matches!(
+            german(page).document_type(),
+            Err(ViacPdfError::MissingField(
+                _,
+                DocumentKind::TransferIn,
+                "valuta date"
+            ))
+        ));
+        let page =
+            "Einlieferung\n12.5\nAnt\nUBS ETF MSCI World\nISIN:\nIE00B4L5Y983\nValuta 02.05.2023";
+        assert!(matches!(
+            german(page).document_type(),
+            Err(ViacPdfError::MissingField(
+                _,
+                DocumentKind::DeliveryIn,
+                "share price"
+            ))
+        ));
+    }
+
+    #[test]
+    fn tax_statement_amounts_follow_their_title() {
+        let p = german(
+            "Steuerauszug 2023\nVertrag\n1000\nSteuerjahr 2023\nVerrechnungssteuer\nCHF\n12.50\n\
+             Quellensteuer ausländischer Dividenden\nwird nicht zurückerstattet\nQuellensteuer\nCHF\n8.20",
+        );
+        let t = p.tax_statement().unwrap();
+        assert_eq!(t.year, 2023);
+        assert!(t.contributions.is_none());
+        assert_eq!(t.withholding_tax.unwrap().to_string(), "CHF 12.50");
+        assert_eq!(t.foreign_withholding_tax.unwrap().to_string(), "CHF 8.20");
+        let p =
+            french("Attestation fiscale\nAnnée fiscale 2022\nCotisations versées\nCHF\n7'056.00");
+        let t = p.tax_statement().unwrap();
+        assert_eq!(t.year, 2022);
+        assert_eq!(t.contributions.unwrap().to_string(), "CHF 7056.00");
+        assert!(german("Steuerauszug\nVerrechnungssteuer\nCHF\n12.50")
+            .tax_statement()
+            .is_none());
+    }
+
+    #[test]
+    fn product_type_names_the_accounts() {
+        let p = german("VIAC Vorsorgestiftung 3a\nVertrag\n1000");
+        assert_eq!(p.product_type(), ProductType::Pillar3a);
+        let p = french("Fondation de libre passage VIAC\nContrat\n1000");
+        assert_eq!(p.product_type(), ProductType::VestedBenefits);
+        assert_eq!(german("Vertrag\n1000").product_type(), ProductType::Unknown);
+        assert_eq!(ProductType::Pillar3a.account_name("12"), "VIAC_3a_12");
+        assert_eq!(ProductType::VestedBenefits.account_name("12"), "VIAC_FZ_12");
+        assert_eq!(ProductType::Unknown.account_name("12"), "VIAC_12");
+    }
+
+    fn trade_with_costs(
+        costs: &[(CostKind, &str)],
+        exchange_rate: Option<&str>,
+    ) -> ViacTransaction {
+        let total_price = money(if exchange_rate.is_some() {
+            "USD 1000.00"
+        } else {
+            "CHF 1000.00"
+        });
+        ViacTransaction {
+            valuta_date: midnight(2023, 3, 1),
+            shares: Decimal::TEN,
+            share_price: Money {
+                currency: total_price.currency,
+                amount: Decimal::ONE_HUNDRED,
+            },
+            total_price,
+            valuta_price: money("CHF 921.50"),
+            costs: costs
+                .iter()
+                .map(|(kind, amount)| TradeCost {
+                    kind: *kind,
+                    amount: money(amount),
+                })
+                .collect(),
+            isin: "IE00B5BMR087".to_string(),
+            share_title: "S&P 500".to_string(),
+            exchange_rate: exchange_rate.map(|rate| ExchangeRate {
+                rate: Decimal::from_str(rate).unwrap(),
+                total_price,
+                pdf_price: money("CHF 920.00"),
+            }),
+        }
+    }
+
+    #[test]
+    fn costs_are_summed_in_the_booked_currency() {
+        let t = trade_with_costs(
+            &[
+                (CostKind::StampDuty, "CHF 1.38"),
+                (CostKind::Brokerage, "USD 0.10"),
+                (CostKind::CurrencyExchange, "CHF 0.03"),
+            ],
+            Some("0.92"),
+        );
+        assert_eq!(t.taxes().unwrap().to_string(), "CHF 1.38");
+        assert_eq!(t.fees().unwrap().to_string(), "CHF 0.12");
+        assert_eq!(t.valuta_without_costs().to_string(), "CHF 920.00");
+        // a foreign cost line without exchange rate can't be converted
+        let t = trade_with_costs(
+            &[
+                (CostKind::StampDuty, "CHF 1.38"),
+                (CostKind::Brokerage, "USD 0.10"),
+            ],
+            None,
+        );
+        assert!(t.fees().is_none());
+        assert_eq!(t.taxes().unwrap().to_string(), "CHF 1.38");
+        assert!(trade_with_costs(&[], None).fees().is_none());
+    }
+
+    #[test]
+    fn cost_lines_read_from_a_trade_page() {
+        let page = "Börsenabrechnung - Kauf\n10\nAnt\nS&P 500\nISIN:\nIE00B5BMR087\n\
+                    Kurs:\nUSD 100.00\nBetrag\nUSD\n1'000.00\nCourtage\nUSD\n0.10\n\
+                    Stempelsteuer\nCHF\n1.38\nFremdwährungsgebühr\nCHF\n0.03\n\
+                    Umrechnungskurs USD/CHF 0.92\nCHF\n920.00\nValuta 01.03.2023\nCHF\n921.50";
+        let ViacDocument::Purchase(t) = german(page).document_type().unwrap() else {
+            panic!("not a purchase");
+        };
+        assert_eq!(t.costs.len(), 3);
+        assert_eq!(t.taxes().unwrap().to_string(), "CHF 1.38");
+        assert_eq!(t.fees().unwrap().to_string(), "CHF 0.12");
+        assert_eq!(t.total_price.to_string(), "USD 1000.00");
+        assert_eq!(t.valuta_without_costs().to_string(), "CHF 920.00");
+        assert_eq!(t.chf_rate().to_string(), "0.92");
+    }
+
+    #[test]
+    fn statement_positions_end_at_the_next_isin() {
+        let lines = [
+            "12.5",
+            "Ant",
+            "CSIF World",
+            "ISIN:",
+            "CH0000000001",
+            "Kurs:",
+            "USD 1'234.50",
+            "Bewertung",
+            "CHF",
+            "14'196.75",
+            "3",
+            "Ant",
+            "CSIF Bonds",
+            "ISIN:",
+            "CH0000000002",
+            "Bewertung",
+            "CHF",
+            "300.00",
+            "1",
+            "Ant",
+            "CSIF EM",
+            "ISIN:",
+            "CH0000000003",
+            "Kurs:",
+            "CHF 99.00",
+        ];
+        let h = holding(&lines, 3, 3, "Kurs:", "Bewertung").unwrap();
+        assert_eq!(h.isin, "CH0000000001");
+        assert_eq!(h.shares, Decimal::from_str("12.5").unwrap());
+        assert_eq!(h.price.to_string(), "USD 1234.50");
+        assert_eq!(h.value.amount, Decimal::from_str("14196.75").unwrap());
+        // no price of its own, the one of the next position is not taken
+        assert!(holding(&lines, 13, 3, "Kurs:", "Bewertung").is_none());
+        // value missing at the end of the statement
+        assert!(holding(&lines, 21, 3, "Kurs:", "Bewertung").is_none());
+    }
+}